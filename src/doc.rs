@@ -0,0 +1,1349 @@
+//! An owned document representation, with a renderer based on Wadler's "prettier printer".
+//!
+//! Every built-in `Pretty` type can be lowered to a [`Doc`] using the [`to_doc`] method.  Unlike
+//! the direct rendering performed by [`write`](../fn.write.html), where each type makes its own
+//! local layout decisions, a `Doc` is a plain data structure which can be inspected, transformed,
+//! and rendered by the classic `best`/`fits` algorithm described by Wadler and Leijen.
+//!
+//! # Examples
+//!
+//! Basic usage:
+//!
+//! ```
+//! use pretty_trait::{Pretty, JoinExt, Group, Sep, delimited, block};
+//! use pretty_trait::doc::{self, Doc};
+//!
+//! let items = delimited(&",".join(Sep(1)), &["lorem", "ipsum"]);
+//! let list = Group::new("[".join(block(items)).join("]"));
+//!
+//! let lowered = list.to_doc();
+//! assert_eq!(doc::to_string(&lowered, None, 4), "[lorem, ipsum]");
+//!
+//! let expected = "\
+//! [
+//!     lorem,
+//!     ipsum
+//! ]";
+//!
+//! assert_eq!(doc::to_string(&lowered, Some(10), 4), expected);
+//! ```
+//!
+//! [`Doc`]: enum.Doc.html
+//! [`to_doc`]: ../trait.Pretty.html#method.to_doc
+
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::rc::Rc;
+
+use {block_width, side_by_side, GroupId, Indentation};
+
+/// A document in an intermediate representation suitable for rendering with [`write`] or
+/// [`to_string`].
+///
+/// # Examples
+///
+/// Building a document by hand:
+///
+/// ```
+/// use pretty_trait::doc::{self, Doc};
+///
+/// let doc = Doc::Group(Box::new(Doc::Concat(vec![
+///     Doc::Text("hello".to_string()),
+///     Doc::Line(1),
+///     Doc::Text("world".to_string()),
+/// ])));
+///
+/// assert_eq!(doc::to_string(&doc, None, 4), "hello world");
+/// assert_eq!(doc::to_string(&doc, Some(8), 4), "hello\nworld");
+/// ```
+///
+/// [`write`]: fn.write.html
+/// [`to_string`]: fn.to_string.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Doc {
+    /// The empty document.
+    Nil,
+
+    /// Literal text, which should not contain any newlines.
+    Text(String),
+
+    /// A possible line break, rendered as the given number of spaces when flat.
+    Line(usize),
+
+    /// A possible line break with the given priority, rendered as the second number of spaces when
+    /// flat.  It is rendered exactly like a [`Line`](#variant.Line), unless the priorities of the
+    /// line breaks in a group are turned into nested groups with
+    /// [`group_priorities`](#method.group_priorities).
+    PriorityLine(usize, usize),
+
+    /// A line break which is rendered even when flat.  An enclosing group is still rendered flat if
+    /// each of its lines fits.
+    HardLine,
+
+    /// Like [`HardLine`](#variant.HardLine), but rendered as the given number of line breaks, less
+    /// any line breaks rendered since the last text, so that consecutive `HardLines` are merged and
+    /// only the largest number of line breaks among them is written.
+    HardLines(usize),
+
+    /// Increase the indentation of the wrapped document by the given number of tab stops.
+    Nest(usize, Box<Doc>),
+
+    /// Set the indentation of the wrapped document to the given number of columns past the column
+    /// at which it starts.
+    Align(usize, Box<Doc>),
+
+    /// Add a prefix string to the indentation of the wrapped document.  An indentation of a fixed
+    /// number of columns can be represented by a prefix made of spaces.
+    Prefix(String, Box<Doc>),
+
+    /// Reset the indentation of the wrapped document to the given number of columns.
+    Dedent(usize, Box<Doc>),
+
+    /// Lay out the wrapped document as if the maximum line length were the given number of
+    /// columns, or the enclosing maximum line length if that is narrower.  The wrapped document is
+    /// still measured against the enclosing maximum line length when an enclosing group decides
+    /// whether or not it fits.
+    MaxLine(usize, Box<Doc>),
+
+    /// Render the wrapped document flat if it fits on the current line, or broken otherwise.
+    Group(Box<Doc>),
+
+    /// Render the line breaks directly inside the wrapped document flat, even if it does not fit.
+    /// Any group nested inside it is still broken if it does not fit.
+    Flat(Box<Doc>),
+
+    /// Render the wrapped document broken, even if it fits, and prevent any enclosing group from
+    /// fitting on one line.
+    Broken(Box<Doc>),
+
+    /// Render the first document if its first line fits on the current line, or the second
+    /// otherwise.
+    Union(Box<Doc>, Box<Doc>),
+
+    /// Render the first document when broken, or the second when flat.
+    FlatAlt(Box<Doc>, Box<Doc>),
+
+    /// Record whether or not the wrapped document, which is usually a group, is rendered broken,
+    /// under the given ID.
+    Label(GroupId, Box<Doc>),
+
+    /// Render the first document if the document labeled with the given ID was rendered broken,
+    /// or the second otherwise.
+    IfBroken(GroupId, Box<Doc>, Box<Doc>),
+
+    /// Defer a document until just before the next line break, or the end of the output.  A line
+    /// suffix cannot be rendered flat, so it causes its enclosing group to break.
+    LineSuffix(Box<Doc>),
+
+    /// Render two documents separately, each as a block of lines starting at column 0, and place
+    /// them side by side, separated by the given number of columns.  The first document is laid out
+    /// within the space remaining on the current line, and the second within the space remaining
+    /// after the widest line of the first and the gap.  Every line after the first starts at the
+    /// column where this document starts.
+    Beside(usize, Box<Doc>, Box<Doc>),
+
+    /// Render a sequence of documents one after another.
+    Concat(Vec<Doc>),
+}
+
+impl Doc {
+    /// Build a document from text which may contain newlines, which are lowered to
+    /// [`HardLine`](#variant.HardLine)s.
+    pub fn lines(text: &str) -> Doc {
+        let mut docs = Vec::new();
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                docs.push(Doc::HardLine);
+            }
+            if !line.is_empty() {
+                docs.push(Doc::Text(line.to_string()));
+            }
+        }
+        match docs.len() {
+            0 => Doc::Nil,
+            1 => docs.pop().unwrap(),
+            _ => Doc::Concat(docs),
+        }
+    }
+
+    /// Wrap each line break in this document which is not inside a nested group in its own group,
+    /// so that it only breaks if the text following it does not fit.
+    pub fn group_lines(self) -> Doc {
+        let group_lines = |doc: Box<Doc>| Box::new(doc.group_lines());
+        match self {
+            Doc::Line(width) | Doc::PriorityLine(_, width) => Doc::Group(Box::new(Doc::Line(width))),
+            Doc::Nest(levels, inner) => Doc::Nest(levels, group_lines(inner)),
+            Doc::Align(offset, inner) => Doc::Align(offset, group_lines(inner)),
+            Doc::Prefix(prefix, inner) => Doc::Prefix(prefix, group_lines(inner)),
+            Doc::Dedent(width, inner) => Doc::Dedent(width, group_lines(inner)),
+            Doc::Union(first, second) => Doc::Union(group_lines(first), group_lines(second)),
+            Doc::FlatAlt(broken, flat) => Doc::FlatAlt(group_lines(broken), group_lines(flat)),
+            Doc::IfBroken(id, then, otherwise) => {
+                Doc::IfBroken(id, group_lines(then), group_lines(otherwise))
+            }
+            Doc::Concat(docs) => Doc::Concat(docs.into_iter().map(Doc::group_lines).collect()),
+            doc => doc,
+        }
+    }
+
+    /// Turn the priorities of the [`PriorityLine`](#variant.PriorityLine)s in this document which
+    /// are not inside a nested group into nested groups, so that when the group containing this
+    /// document breaks, only the line breaks with the lowest priority break along with any
+    /// ordinary line breaks.  The text between them is grouped so that the line breaks with the
+    /// next lowest priority only break if it does not fit, and so on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pretty_trait::doc::{self, Doc};
+    ///
+    /// let text = |text: &str| Doc::Text(text.to_string());
+    /// let condition = Doc::Group(Box::new(Doc::Concat(vec![
+    ///     text("aaaa"),
+    ///     Doc::PriorityLine(1, 1),
+    ///     text("&& bbbb"),
+    ///     Doc::PriorityLine(0, 1),
+    ///     text("|| cccc"),
+    /// ])));
+    ///
+    /// assert_eq!(doc::to_string(&condition, Some(14), 4), "aaaa\n&& bbbb\n|| cccc");
+    ///
+    /// let prioritized = match condition {
+    ///     Doc::Group(content) => Doc::Group(Box::new(content.group_priorities())),
+    ///     _ => unreachable!(),
+    /// };
+    ///
+    /// assert_eq!(doc::to_string(&prioritized, Some(14), 4), "aaaa && bbbb\n|| cccc");
+    /// ```
+    pub fn group_priorities(self) -> Doc {
+        let mut priorities = Vec::new();
+        self.collect_priorities(&mut priorities);
+        priorities.sort();
+        priorities.dedup();
+        match priorities.split_first() {
+            Some((&lowest, higher)) => self.nest_priorities(lowest, higher),
+            None => self,
+        }
+    }
+
+    fn collect_priorities(&self, priorities: &mut Vec<usize>) {
+        match *self {
+            Doc::PriorityLine(priority, _) => priorities.push(priority),
+            Doc::Nest(_, ref inner) |
+            Doc::Align(_, ref inner) |
+            Doc::Prefix(_, ref inner) |
+            Doc::Dedent(_, ref inner) => inner.collect_priorities(priorities),
+            Doc::Union(ref first, ref second) |
+            Doc::FlatAlt(ref first, ref second) |
+            Doc::IfBroken(_, ref first, ref second) => {
+                first.collect_priorities(priorities);
+                second.collect_priorities(priorities);
+            }
+            Doc::Concat(ref docs) => {
+                for doc in docs {
+                    doc.collect_priorities(priorities);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Check whether this document contains a line break, not inside a nested group, which breaks
+    // along with the line breaks of the given priority.
+    fn breaks_with(&self, priority: usize) -> bool {
+        match *self {
+            Doc::Line(_) => true,
+            Doc::PriorityLine(line_priority, _) => line_priority <= priority,
+            Doc::Nest(_, ref inner) |
+            Doc::Align(_, ref inner) |
+            Doc::Prefix(_, ref inner) |
+            Doc::Dedent(_, ref inner) => inner.breaks_with(priority),
+            Doc::Union(ref first, ref second) |
+            Doc::FlatAlt(ref first, ref second) |
+            Doc::IfBroken(_, ref first, ref second) => {
+                first.breaks_with(priority) || second.breaks_with(priority)
+            }
+            Doc::Concat(ref docs) => docs.iter().any(|doc| doc.breaks_with(priority)),
+            _ => false,
+        }
+    }
+
+    // Turn the line breaks which break along with the given priority into ordinary line breaks, and
+    // group the documents between them by the `higher` priorities.
+    fn nest_priorities(self, priority: usize, higher: &[usize]) -> Doc {
+        if !self.breaks_with(priority) {
+            return self.group_by_priority(higher);
+        }
+        let nest = |doc: Box<Doc>| Box::new(doc.nest_priorities(priority, higher));
+        match self {
+            Doc::PriorityLine(_, width) => Doc::Line(width),
+            Doc::Nest(levels, inner) => Doc::Nest(levels, nest(inner)),
+            Doc::Align(offset, inner) => Doc::Align(offset, nest(inner)),
+            Doc::Prefix(prefix, inner) => Doc::Prefix(prefix, nest(inner)),
+            Doc::Dedent(width, inner) => Doc::Dedent(width, nest(inner)),
+            Doc::Union(first, second) => Doc::Union(nest(first), nest(second)),
+            Doc::FlatAlt(broken, flat) => Doc::FlatAlt(nest(broken), nest(flat)),
+            Doc::IfBroken(id, then, otherwise) => Doc::IfBroken(id, nest(then), nest(otherwise)),
+            Doc::Concat(docs) => {
+                let mut flattened = Vec::new();
+                flatten_concat(docs, &mut flattened);
+                let mut result = Vec::new();
+                let mut run = Vec::new();
+                for doc in flattened {
+                    if doc.breaks_with(priority) {
+                        if !run.is_empty() {
+                            result.push(Doc::Concat(mem::take(&mut run)).group_by_priority(higher));
+                        }
+                        result.push(doc.nest_priorities(priority, higher));
+                    } else {
+                        run.push(doc);
+                    }
+                }
+                if !run.is_empty() {
+                    result.push(Doc::Concat(run).group_by_priority(higher));
+                }
+                Doc::Concat(result)
+            }
+            doc => doc,
+        }
+    }
+
+    // Group this document if it contains line breaks with the lowest of the given priorities.
+    fn group_by_priority(self, priorities: &[usize]) -> Doc {
+        match priorities.split_first() {
+            Some((&lowest, higher)) if self.breaks_with(lowest) => {
+                Doc::Group(Box::new(self.nest_priorities(lowest, higher)))
+            }
+            _ => self,
+        }
+    }
+}
+
+/// Append the given documents to `result`, replacing each nested `Concat` with its contents.
+fn flatten_concat(docs: Vec<Doc>, result: &mut Vec<Doc>) {
+    for doc in docs {
+        match doc {
+            Doc::Concat(docs) => flatten_concat(docs, result),
+            doc => result.push(doc),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+    // Line breaks directly inside are rendered flat, but nested groups decide for themselves
+    // whether or not they fit.
+    Unbroken,
+}
+
+// The indentation, mode and maximum line length to render a document with.
+type Command<'a> = (Indentation, Mode, Option<usize>, &'a Doc);
+
+/// Check whether `command`, starting at `column` on a line whose text after the indentation starts
+/// at `line_start`, followed by `rest`, fits within the maximum line length of `command` and the
+/// ribbon width up to the first line break rendered in broken mode.
+///
+/// Hard line breaks rendered in any other mode do not end the check, so that a group containing
+/// them only fits if each of its lines does, just as with [`Size::Lines`] in the `Pretty` API.
+///
+/// [`Size::Lines`]: ../enum.Size.html#variant.Lines
+fn fits(
+    tab_size: usize,
+    ribbon: Option<usize>,
+    mut column: usize,
+    mut line_start: usize,
+    command: Command,
+    rest: &[Command],
+    groups: &HashMap<GroupId, bool>,
+) -> bool {
+    let (indent, mode, max_line, doc) = command;
+    if max_line.is_none() && ribbon.is_none() {
+        return true;
+    }
+    let too_wide = |width, column, line_start| {
+        exceeds(width, column, line_start, max_line, ribbon)
+    };
+    // Only the width of the indentation matters here.
+    let mut stack = vec![(indent.width(), mode, doc)];
+    let mut rest_index = rest.len();
+    loop {
+        let (indent, mode, doc) = match stack.pop() {
+            Some(command) => command,
+            None if rest_index > 0 => {
+                rest_index -= 1;
+                let (ref indent, mode, _, doc) = rest[rest_index];
+                (indent.width(), mode, doc)
+            }
+            None => return true,
+        };
+        match *doc {
+            Doc::Nil | Doc::HardLines(0) => {}
+            Doc::Text(ref text) => {
+                let width = text.chars().count();
+                if too_wide(width, column, line_start) {
+                    return false;
+                }
+                column += width;
+            }
+            Doc::Line(width) | Doc::PriorityLine(_, width) if mode != Mode::Break => {
+                if too_wide(width, column, line_start) {
+                    return false;
+                }
+                column += width;
+            }
+            Doc::Line(_) | Doc::PriorityLine(..) | Doc::HardLine | Doc::HardLines(_)
+                if mode == Mode::Break =>
+            {
+                return true
+            }
+            Doc::Line(_) | Doc::PriorityLine(..) | Doc::HardLine | Doc::HardLines(_) => {
+                column = indent;
+                line_start = indent;
+            }
+            Doc::LineSuffix(_) => {
+                if mode != Mode::Break {
+                    return false;
+                }
+            }
+            Doc::Flat(ref inner) => stack.push((indent, Mode::Flat, inner)),
+            Doc::Broken(ref inner) => {
+                if mode != Mode::Break {
+                    return false;
+                }
+                stack.push((indent, Mode::Break, inner));
+            }
+            Doc::Nest(levels, ref inner) => stack.push((indent + levels * tab_size, mode, inner)),
+            Doc::Align(offset, ref inner) => stack.push((column + offset, mode, inner)),
+            Doc::Prefix(ref prefix, ref inner) => {
+                stack.push((indent + prefix.chars().count(), mode, inner))
+            }
+            Doc::Dedent(width, ref inner) => stack.push((width, mode, inner)),
+            // The contents are measured against the enclosing maximum line length.
+            Doc::MaxLine(_, ref inner) => stack.push((indent, mode, inner)),
+            // A group following the command in a mode which lets it decide for itself may break.
+            Doc::Group(ref inner) if mode == Mode::Unbroken => {
+                stack.push((indent, Mode::Break, inner))
+            }
+            Doc::Group(ref inner) | Doc::Union(ref inner, _) | Doc::Label(_, ref inner) => {
+                stack.push((indent, mode, inner))
+            }
+            Doc::FlatAlt(ref broken, ref flat) => {
+                stack.push((indent, mode, if mode == Mode::Break { broken } else { flat }))
+            }
+            Doc::IfBroken(id, ref then, ref otherwise) => {
+                let broken = groups.get(&id) == Some(&true);
+                stack.push((indent, mode, if broken { then } else { otherwise }))
+            }
+            Doc::Beside(gap, ref left, ref right) => {
+                let available = max_line.map(|max| max.saturating_sub(column));
+                let lines = beside_lines(gap, left, right, available, |doc, max_line| {
+                    render_block(doc, max_line, ribbon, tab_size, groups)
+                });
+                // As with `Broken`, a block of several lines keeps any enclosing group from fitting
+                // on one line.
+                if lines.len() > 1 && mode != Mode::Break {
+                    return false;
+                }
+                let width = lines[0].chars().count();
+                if too_wide(width, column, line_start) {
+                    return false;
+                }
+                if lines.len() > 1 {
+                    return true;
+                }
+                column += width;
+            }
+            Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, mode, doc));
+                }
+            }
+        }
+    }
+}
+
+/// Check whether text of the given width, starting at `column` on a line whose text after the
+/// indentation starts at `line_start`, would exceed the maximum line length or the ribbon width.
+fn exceeds(
+    width: usize,
+    column: usize,
+    line_start: usize,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+) -> bool {
+    let too_long = match max_line {
+        Some(max) => width > max.saturating_sub(column),
+        None => false,
+    };
+    let too_wide = match ribbon {
+        Some(ribbon) => width > ribbon.saturating_sub(column.saturating_sub(line_start)),
+        None => false,
+    };
+    too_long || too_wide
+}
+
+/// The narrower of a maximum line length and a [`MaxLine`] limit.
+///
+/// [`MaxLine`]: enum.Doc.html#variant.MaxLine
+fn narrower(max_line: Option<usize>, width: usize) -> usize {
+    max_line.map_or(width, |max| cmp::min(max, width))
+}
+
+/// The mode in which to render the contents of a [`MaxLine`].  Even inside a group which fits, the
+/// groups nested in the contents must decide for themselves whether they fit within the narrower
+/// limit.
+///
+/// [`MaxLine`]: enum.Doc.html#variant.MaxLine
+fn unbroken_within_limit(mode: Mode) -> Mode {
+    if mode == Mode::Flat {
+        Mode::Unbroken
+    } else {
+        mode
+    }
+}
+
+/// Render the blocks of a [`Beside`] with `render`, given the maximum line length for the first
+/// block, and merge their lines.
+///
+/// [`Beside`]: enum.Doc.html#variant.Beside
+fn beside_lines<F>(
+    gap: usize,
+    left: &Doc,
+    right: &Doc,
+    max_line: Option<usize>,
+    mut render: F,
+) -> Vec<String>
+where
+    F: FnMut(&Doc, Option<usize>) -> Vec<String>,
+{
+    let left = render(left, max_line);
+    let right_max = max_line.map(|max| max.saturating_sub(block_width(&left) + gap));
+    let right = render(right, right_max);
+    side_by_side(gap, &left, &right)
+}
+
+/// Render a document on its own with [`write`], as a block of lines starting at column 0.
+///
+/// [`write`]: fn.write.html
+fn render_block(
+    doc: &Doc,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+    groups: &HashMap<GroupId, bool>,
+) -> Vec<String> {
+    let mut result = Vec::new();
+    render(&mut result, doc, max_line, ribbon, tab_size, groups.clone())
+        .expect("Writing to a string should not fail");
+    split_lines(result)
+}
+
+fn split_lines(result: Vec<u8>) -> Vec<String> {
+    String::from_utf8(result)
+        .expect("Invalid UTF8")
+        .split('\n')
+        .map(str::to_string)
+        .collect()
+}
+
+/// Render a document to an arbitrary `io::Write` handle.
+///
+/// The document as a whole is treated as a group, so it will only be rendered broken if it does not
+/// fit within `max_line`.
+pub fn write(
+    writer: &mut dyn io::Write,
+    doc: &Doc,
+    max_line: Option<usize>,
+    tab_size: usize,
+) -> io::Result<()> {
+    write_with_ribbon(writer, doc, max_line, None, tab_size)
+}
+
+/// Render a document to an arbitrary `io::Write` handle, limiting the number of characters on each
+/// line not counting indentation, as well as the total line length.
+///
+/// A group is only rendered flat if each of its lines fits within both `max_line` and, once the
+/// indentation is discounted, the `ribbon` width.  Passing `None` is equivalent to calling
+/// [`write`].
+///
+/// # Examples
+///
+/// ```
+/// use pretty_trait::doc::{self, Doc};
+///
+/// let call = Doc::Group(Box::new(Doc::Concat(vec![
+///     Doc::Text("foo(a,".to_string()),
+///     Doc::Line(1),
+///     Doc::Text("b)".to_string()),
+/// ])));
+/// let nested = Doc::Prefix("        ".to_string(), Box::new(Doc::Concat(vec![Doc::HardLine, call])));
+///
+/// assert_eq!(doc::to_string(&nested, Some(20), 4), "\n        foo(a, b)");
+/// assert_eq!(doc::to_string_with_ribbon(&nested, Some(20), Some(8), 4), "\n        foo(a,\n        b)");
+/// ```
+///
+/// [`write`]: fn.write.html
+pub fn write_with_ribbon(
+    writer: &mut dyn io::Write,
+    doc: &Doc,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> io::Result<()> {
+    render(writer, doc, max_line, ribbon, tab_size, HashMap::new())
+}
+
+/// Render a document like [`write_with_ribbon`], knowing whether each of the given labeled groups
+/// was broken.
+///
+/// [`write_with_ribbon`]: fn.write_with_ribbon.html
+fn render(
+    writer: &mut dyn io::Write,
+    doc: &Doc,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+    mut groups: HashMap<GroupId, bool>,
+) -> io::Result<()> {
+    let command = (Indentation::new(), Mode::Flat, max_line, doc);
+    let mode = if fits(tab_size, ribbon, 0, 0, command, &[], &groups) {
+        Mode::Flat
+    } else {
+        Mode::Break
+    };
+    let mut column = 0;
+    // The column at which the text after the indentation of the current line starts.
+    let mut line_start = 0;
+    let mut stack: Vec<Command> = vec![(Indentation::new(), mode, max_line, doc)];
+    let mut line_suffix: Vec<Command> = Vec::new();
+    // The indentation after the last line break is only written along with the text following it,
+    // so that blank lines carry no trailing whitespace.
+    let mut pending_indent: Option<Indentation> = None;
+    // The number of line breaks written since the last text, for merging `HardLines`.
+    let mut line_breaks = 0;
+    loop {
+        let (indent, mode, max_line, doc) = match stack.pop() {
+            Some(command) => command,
+            None if !line_suffix.is_empty() => {
+                stack.extend(line_suffix.drain(..).rev());
+                continue;
+            }
+            None => break,
+        };
+        match *doc {
+            Doc::Nil => {}
+            Doc::Text(ref text) => {
+                if !text.is_empty() {
+                    write_indent(writer, &mut pending_indent)?;
+                    line_breaks = 0;
+                }
+                write!(writer, "{}", text)?;
+                column += text.chars().count();
+            }
+            Doc::Line(width) | Doc::PriorityLine(_, width) if mode != Mode::Break => {
+                if width > 0 {
+                    write_indent(writer, &mut pending_indent)?;
+                    line_breaks = 0;
+                }
+                for _ in 0..width {
+                    write!(writer, " ")?;
+                }
+                column += width;
+            }
+            Doc::HardLines(count) if count <= line_breaks => {
+                // The line breaks are merged with those already written, but the line after them
+                // is still indented by this one.
+                if count > 0 {
+                    column = indent.width();
+                    line_start = column;
+                    pending_indent = Some(indent);
+                }
+            }
+            Doc::Line(_) | Doc::PriorityLine(..) | Doc::HardLine | Doc::HardLines(_)
+                if !line_suffix.is_empty() =>
+            {
+                // Render the pending suffixes first, and then come back to this line break.
+                stack.push((indent, mode, max_line, doc));
+                stack.extend(line_suffix.drain(..).rev());
+            }
+            Doc::Line(_) | Doc::PriorityLine(..) | Doc::HardLine | Doc::HardLines(_) => {
+                let count = match *doc {
+                    Doc::HardLines(count) => count - line_breaks,
+                    _ => 1,
+                };
+                for _ in 0..count {
+                    write_line_break(writer, &mut pending_indent)?;
+                    pending_indent = Some(indent.clone());
+                }
+                line_breaks += count;
+                column = indent.width();
+                line_start = column;
+            }
+            Doc::Nest(levels, ref inner) => {
+                stack.push((indent.with_spaces(levels * tab_size), mode, max_line, inner))
+            }
+            Doc::Align(offset, ref inner) => {
+                stack.push((indent.aligned_to(column + offset), mode, max_line, inner))
+            }
+            Doc::Prefix(ref prefix, ref inner) => {
+                stack.push((indent.with_prefix(prefix), mode, max_line, inner))
+            }
+            Doc::Dedent(width, ref inner) => {
+                stack.push((Indentation::spaces(width), mode, max_line, inner))
+            }
+            Doc::MaxLine(width, ref inner) => {
+                let mode = unbroken_within_limit(mode);
+                stack.push((indent, mode, Some(narrower(max_line, width)), inner))
+            }
+            Doc::Flat(ref inner) => {
+                // Inside a group which fits, everything is flat anyway.
+                let mode = if mode == Mode::Flat {
+                    Mode::Flat
+                } else {
+                    Mode::Unbroken
+                };
+                stack.push((indent, mode, max_line, inner))
+            }
+            Doc::Broken(ref inner) => stack.push((indent, Mode::Break, max_line, inner)),
+            Doc::Group(ref inner) => {
+                let command = (indent.clone(), Mode::Flat, max_line, &**inner);
+                let mode = if mode == Mode::Flat ||
+                    fits(tab_size, ribbon, column, line_start, command, &stack, &groups)
+                {
+                    Mode::Flat
+                } else {
+                    Mode::Break
+                };
+                stack.push((indent, mode, max_line, inner));
+            }
+            Doc::Label(id, ref inner) => {
+                let inner_mode = match **inner {
+                    Doc::Group(ref content) => {
+                        let command = (indent.clone(), Mode::Flat, max_line, &**content);
+                        if mode == Mode::Flat ||
+                            fits(tab_size, ribbon, column, line_start, command, &stack, &groups)
+                        {
+                            Mode::Flat
+                        } else {
+                            Mode::Break
+                        }
+                    }
+                    Doc::Flat(_) => Mode::Flat,
+                    Doc::Broken(_) => Mode::Break,
+                    _ => mode,
+                };
+                groups.insert(id, inner_mode == Mode::Break);
+                match **inner {
+                    Doc::Group(ref content) => stack.push((indent, inner_mode, max_line, content)),
+                    _ => stack.push((indent, mode, max_line, inner)),
+                }
+            }
+            Doc::IfBroken(id, ref then, ref otherwise) => {
+                let broken = groups.get(&id) == Some(&true);
+                stack.push((indent, mode, max_line, if broken { then } else { otherwise }))
+            }
+            Doc::Union(ref first, ref second) => {
+                let command = (indent.clone(), mode, max_line, &**first);
+                if fits(tab_size, ribbon, column, line_start, command, &stack, &groups) {
+                    stack.push((indent, mode, max_line, first));
+                } else {
+                    stack.push((indent, mode, max_line, second));
+                }
+            }
+            Doc::FlatAlt(ref broken, ref flat) => {
+                let inner = if mode == Mode::Break { broken } else { flat };
+                stack.push((indent, mode, max_line, inner))
+            }
+            Doc::LineSuffix(ref inner) => line_suffix.push((indent, mode, max_line, inner)),
+            Doc::Beside(gap, ref left, ref right) => {
+                let available = max_line.map(|max| max.saturating_sub(column));
+                let lines = beside_lines(gap, left, right, available, |doc, max_line| {
+                    render_block(doc, max_line, ribbon, tab_size, &groups)
+                });
+                let indent = indent.aligned_to(column);
+                for (i, line) in lines.iter().enumerate() {
+                    if i > 0 {
+                        write_line_break(writer, &mut pending_indent)?;
+                        pending_indent = Some(indent.clone());
+                        line_breaks += 1;
+                        column = indent.width();
+                        line_start = column;
+                    }
+                    if !line.is_empty() {
+                        write_indent(writer, &mut pending_indent)?;
+                        line_breaks = 0;
+                    }
+                    write!(writer, "{}", line)?;
+                    column += line.chars().count();
+                }
+            }
+            Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent.clone(), mode, max_line, doc));
+                }
+            }
+        }
+    }
+    write_indent(writer, &mut pending_indent)
+}
+
+/// Write the indentation following a line break, if it has not been written yet.
+fn write_indent(writer: &mut dyn io::Write, pending: &mut Option<Indentation>) -> io::Result<()> {
+    match pending.take() {
+        Some(indent) => write!(writer, "{}", indent.as_str()),
+        None => Ok(()),
+    }
+}
+
+/// Write a line break.  If the line being ended is blank, any prefix in its indentation is written
+/// first, without trailing whitespace.
+fn write_line_break(
+    writer: &mut dyn io::Write,
+    pending: &mut Option<Indentation>,
+) -> io::Result<()> {
+    if let Some(indent) = pending.take() {
+        write!(writer, "{}", indent.as_str().trim_end())?;
+    }
+    writeln!(writer)
+}
+
+/// Render a document to an owned string and return it.
+pub fn to_string(doc: &Doc, max_line: Option<usize>, tab_size: usize) -> String {
+    to_string_with_ribbon(doc, max_line, None, tab_size)
+}
+
+/// Render a document to an owned string using [`write_with_ribbon`], and return it.
+///
+/// [`write_with_ribbon`]: fn.write_with_ribbon.html
+pub fn to_string_with_ribbon(
+    doc: &Doc,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> String {
+    let mut result = Vec::new();
+    write_with_ribbon(&mut result, doc, max_line, ribbon, tab_size)
+        .expect("Writing to a string should not fail");
+    String::from_utf8(result).expect("Invalid UTF8")
+}
+
+/// A single piece of rendered output, used to reconstruct the layout chosen by `write_optimal`.
+#[derive(Clone, Debug)]
+enum Piece<'a> {
+    Text(Cow<'a, str>),
+    Spaces(usize),
+    Newline(Indentation),
+    // A change to the indentation following the last line break, when more line breaks are merged
+    // with it.
+    Indent(Indentation),
+}
+
+/// A persistent list of output pieces, in reverse order, shared between candidate layouts.
+struct Output<'a> {
+    piece: Piece<'a>,
+    prev: Option<Rc<Output<'a>>>,
+}
+
+impl<'a> Drop for Output<'a> {
+    fn drop(&mut self) {
+        // Unlink iteratively, so that dropping a long output does not overflow the stack.
+        let mut prev = self.prev.take();
+        while let Some(output) = prev {
+            match Rc::try_unwrap(output) {
+                Ok(mut output) => prev = output.prev.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+/// A persistent list of the labeled groups a candidate layout has decided to break or not.
+struct Decision {
+    id: GroupId,
+    broken: bool,
+    prev: Option<Rc<Decision>>,
+}
+
+/// A partially rendered candidate layout.
+#[derive(Clone)]
+struct State<'a> {
+    // The number of line suffixes rendered in flat mode, which should have forced a break.
+    flat_suffixes: usize,
+    column: usize,
+    // The column at which the text after the indentation of the current line starts.
+    line_start: usize,
+    overflow: usize,
+    lines: usize,
+    // The number of line breaks since the last text, for merging `HardLines`.
+    line_breaks: usize,
+    // The number of times the second branch of a union was chosen, so that the first branch is
+    // preferred between layouts which are otherwise equally good.
+    fallbacks: usize,
+    output: Option<Rc<Output<'a>>>,
+    groups: Option<Rc<Decision>>,
+    line_suffix: Vec<Command<'a>>,
+}
+
+impl<'a> State<'a> {
+    fn cost(&self) -> (usize, usize, usize, usize) {
+        (self.flat_suffixes, self.overflow, self.lines, self.fallbacks)
+    }
+
+    fn record(&mut self, id: GroupId, broken: bool) {
+        self.groups = Some(Rc::new(Decision {
+            id,
+            broken,
+            prev: self.groups.take(),
+        }));
+    }
+
+    fn group_broken(&self, id: GroupId) -> bool {
+        let mut decision = self.groups.as_ref();
+        while let Some(node) = decision {
+            if node.id == id {
+                return node.broken;
+            }
+            decision = node.prev.as_ref();
+        }
+        false
+    }
+
+    fn push(&mut self, piece: Piece<'a>, max_line: Option<usize>, ribbon: Option<usize>) {
+        let excess = |column: usize, line_start: usize| {
+            max_line.map_or(0, |max| column.saturating_sub(max)) +
+                ribbon.map_or(0, |ribbon| column.saturating_sub(line_start + ribbon))
+        };
+        let old_excess = excess(self.column, self.line_start);
+        match piece {
+            Piece::Text(ref text) => self.column += text.chars().count(),
+            Piece::Spaces(width) => self.column += width,
+            Piece::Newline(ref indent) => {
+                self.column = indent.width();
+                self.line_start = self.column;
+                self.lines += 1;
+                self.line_breaks += 1;
+            }
+            Piece::Indent(ref indent) => {
+                self.column = indent.width();
+                self.line_start = self.column;
+            }
+        }
+        let blank = match piece {
+            Piece::Text(ref text) => text.is_empty(),
+            Piece::Spaces(width) => width == 0,
+            Piece::Newline(_) | Piece::Indent(_) => true,
+        };
+        if !blank {
+            self.line_breaks = 0;
+        }
+        let new_excess = excess(self.column, self.line_start);
+        match piece {
+            Piece::Newline(_) => self.overflow += new_excess,
+            // The excess of the indentation it replaces was already counted.
+            Piece::Indent(_) => self.overflow = self.overflow - old_excess + new_excess,
+            _ => self.overflow += new_excess - old_excess,
+        }
+        self.output = Some(Rc::new(Output {
+            piece,
+            prev: self.output.take(),
+        }));
+    }
+}
+
+/// Discard every candidate layout which is no better than another layout ending at the same or an
+/// earlier column.  Such a layout can never lead to a better final result, because starting further
+/// to the left never makes the rest of the document overflow more.  With a `ribbon` width, the
+/// other layout must also have used no more of the ribbon on its current line.
+fn prune(mut states: Vec<State>, ribbon: Option<usize>) -> Vec<State> {
+    states.sort_by_key(|state| (state.column, state.cost()));
+    let used = |state: &State| {
+        if ribbon.is_some() {
+            state.column.saturating_sub(state.line_start)
+        } else {
+            0
+        }
+    };
+    let mut best: Vec<(usize, (usize, usize, usize, usize))> = Vec::new();
+    states.retain(|state| {
+        let keep = !best
+            .iter()
+            .any(|&(best_used, best_cost)| best_used <= used(state) && best_cost <= state.cost());
+        if keep {
+            best.retain(|&(best_used, best_cost)| best_used < used(state) || best_cost < state.cost());
+            best.push((used(state), state.cost()));
+        }
+        keep
+    });
+    states
+}
+
+fn layouts<'a>(
+    doc: &'a Doc,
+    indent: &Indentation,
+    mode: Mode,
+    mut states: Vec<State<'a>>,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> Vec<State<'a>> {
+    match *doc {
+        Doc::Nil => states,
+        Doc::Text(ref text) => {
+            for state in &mut states {
+                state.push(Piece::Text(Cow::Borrowed(text)), max_line, ribbon);
+            }
+            states
+        }
+        Doc::Line(width) | Doc::PriorityLine(_, width) if mode != Mode::Break => {
+            for state in &mut states {
+                state.push(Piece::Spaces(width), max_line, ribbon);
+            }
+            states
+        }
+        Doc::Line(_) | Doc::PriorityLine(..) | Doc::HardLine => {
+            let mut states = flush_line_suffix(states, ribbon, tab_size);
+            for state in &mut states {
+                state.push(Piece::Newline(indent.clone()), max_line, ribbon);
+            }
+            states
+        }
+        Doc::HardLines(count) => {
+            let mut states = flush_line_suffix(states, ribbon, tab_size);
+            for state in &mut states {
+                if count > 0 && count <= state.line_breaks {
+                    state.push(Piece::Indent(indent.clone()), max_line, ribbon);
+                }
+                for _ in state.line_breaks..count {
+                    state.push(Piece::Newline(indent.clone()), max_line, ribbon);
+                }
+            }
+            states
+        }
+        Doc::Nest(levels, ref inner) => {
+            let indent = indent.with_spaces(levels * tab_size);
+            layouts(inner, &indent, mode, states, max_line, ribbon, tab_size)
+        }
+        Doc::Align(offset, ref inner) => {
+            // Each candidate may have reached a different column, so each one is aligned
+            // separately.
+            let mut results = Vec::new();
+            for state in states {
+                let indent = indent.aligned_to(state.column + offset);
+                results.extend(layouts(inner, &indent, mode, vec![state], max_line, ribbon, tab_size));
+            }
+            prune(results, ribbon)
+        }
+        Doc::Prefix(ref prefix, ref inner) => {
+            let indent = indent.with_prefix(prefix);
+            layouts(inner, &indent, mode, states, max_line, ribbon, tab_size)
+        }
+        Doc::Dedent(width, ref inner) => {
+            let indent = Indentation::spaces(width);
+            layouts(inner, &indent, mode, states, max_line, ribbon, tab_size)
+        }
+        Doc::MaxLine(width, ref inner) => {
+            let max_line = Some(narrower(max_line, width));
+            layouts(inner, indent, unbroken_within_limit(mode), states, max_line, ribbon, tab_size)
+        }
+        Doc::Flat(ref inner) => {
+            let mode = if mode == Mode::Flat {
+                Mode::Flat
+            } else {
+                Mode::Unbroken
+            };
+            layouts(inner, indent, mode, states, max_line, ribbon, tab_size)
+        }
+        Doc::Broken(ref inner) => layouts(inner, indent, Mode::Break, states, max_line, ribbon, tab_size),
+        // Inside a group rendered flat, everything is flat anyway, so only groups in other modes
+        // need to try both layouts.
+        Doc::Group(ref inner) if mode == Mode::Flat => {
+            layouts(inner, indent, Mode::Flat, states, max_line, ribbon, tab_size)
+        }
+        Doc::Group(ref inner) => {
+            let mut flat = layouts(inner, indent, Mode::Flat, states.clone(), max_line, ribbon, tab_size);
+            flat.extend(layouts(inner, indent, Mode::Break, states, max_line, ribbon, tab_size));
+            prune(flat, ribbon)
+        }
+        Doc::Union(ref first, ref second) => {
+            let mut results = layouts(first, indent, mode, states.clone(), max_line, ribbon, tab_size);
+            for state in &mut states {
+                state.fallbacks += 1;
+            }
+            results.extend(layouts(second, indent, mode, states, max_line, ribbon, tab_size));
+            prune(results, ribbon)
+        }
+        Doc::FlatAlt(ref broken, ref flat) => {
+            let inner = if mode == Mode::Break { broken } else { flat };
+            layouts(inner, indent, mode, states, max_line, ribbon, tab_size)
+        }
+        Doc::Label(id, ref inner) => {
+            if let Doc::Group(ref content) = **inner {
+                if mode == Mode::Flat {
+                    for state in &mut states {
+                        state.record(id, false);
+                    }
+                    return layouts(content, indent, Mode::Flat, states, max_line, ribbon, tab_size);
+                }
+                // Record each mode of the group in a separate set of candidates.
+                let mut flat = states.clone();
+                for state in &mut flat {
+                    state.record(id, false);
+                }
+                for state in &mut states {
+                    state.record(id, true);
+                }
+                let mut results = layouts(content, indent, Mode::Flat, flat, max_line, ribbon, tab_size);
+                results.extend(layouts(content, indent, Mode::Break, states, max_line, ribbon, tab_size));
+                prune(results, ribbon)
+            } else {
+                let broken = match **inner {
+                    Doc::Flat(_) => false,
+                    Doc::Broken(_) => true,
+                    _ => mode == Mode::Break,
+                };
+                for state in &mut states {
+                    state.record(id, broken);
+                }
+                layouts(inner, indent, mode, states, max_line, ribbon, tab_size)
+            }
+        }
+        Doc::IfBroken(id, ref then, ref otherwise) => {
+            let (broken, unbroken) = states.into_iter().partition(|state| state.group_broken(id));
+            let mut results = layouts(then, indent, mode, broken, max_line, ribbon, tab_size);
+            results.extend(layouts(otherwise, indent, mode, unbroken, max_line, ribbon, tab_size));
+            prune(results, ribbon)
+        }
+        Doc::LineSuffix(ref inner) => {
+            for state in &mut states {
+                if mode != Mode::Break {
+                    state.flat_suffixes += 1;
+                }
+                state.line_suffix.push((indent.clone(), mode, max_line, inner));
+            }
+            states
+        }
+        Doc::Beside(gap, ref left, ref right) => {
+            for state in &mut states {
+                let available = max_line.map(|max| max.saturating_sub(state.column));
+                let groups = state.groups.clone();
+                let lines = beside_lines(gap, left, right, available, |doc, max_line| {
+                    let mut result = Vec::new();
+                    let layout = best_layout(doc, max_line, ribbon, tab_size, groups.clone());
+                    write_layout(&mut result, &layout).expect("Writing to a string should not fail");
+                    split_lines(result)
+                });
+                let indent = indent.aligned_to(state.column);
+                for (i, line) in lines.into_iter().enumerate() {
+                    if i > 0 {
+                        state.push(Piece::Newline(indent.clone()), max_line, ribbon);
+                    }
+                    state.push(Piece::Text(Cow::Owned(line)), max_line, ribbon);
+                }
+            }
+            prune(states, ribbon)
+        }
+        Doc::Concat(ref docs) => {
+            docs.iter().fold(states, |states, doc| {
+                layouts(doc, indent, mode, states, max_line, ribbon, tab_size)
+            })
+        }
+    }
+}
+
+/// Lay out the pending line suffixes of each candidate, ready for a line break or the end of the
+/// output.
+fn flush_line_suffix<'a>(
+    states: Vec<State<'a>>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> Vec<State<'a>> {
+    if states.iter().all(|state| state.line_suffix.is_empty()) {
+        return states;
+    }
+    let mut results = Vec::new();
+    for mut state in states {
+        let line_suffix = mem::take(&mut state.line_suffix);
+        let flushed = line_suffix.iter().fold(
+            vec![state],
+            |states, &(ref indent, mode, max_line, doc)| {
+                layouts(doc, indent, mode, states, max_line, ribbon, tab_size)
+            },
+        );
+        results.extend(flushed);
+    }
+    prune(results, ribbon)
+}
+
+/// Render a document to an arbitrary `io::Write` handle, choosing the layout with the least total
+/// overflow beyond `max_line`, and then the fewest lines.  Between layouts which are otherwise
+/// equally good, the first branch of each [`Union`] is preferred.
+///
+/// Unlike [`write`], which decides how to render each group by looking only at the current line,
+/// this considers every combination of broken and unbroken groups (and every branch of every
+/// [`Union`]), so it will find a layout which fits whenever one exists.  As with [`write`], a group
+/// nested inside a group rendered flat is rendered flat too, so only groups whose enclosing group
+/// is broken need to try both layouts.  Candidate layouts are pruned as they are built, so the
+/// search takes polynomial rather than exponential time, even for deeply nested groups, but it is
+/// still considerably slower than [`write`].  Pruning does not take into account which labeled
+/// groups each candidate broke, so documents containing [`IfBroken`] may not be laid out strictly
+/// optimally.  Nor does it take into account pending [`LineSuffix`]es.
+///
+/// [`write`]: fn.write.html
+/// [`Union`]: enum.Doc.html#variant.Union
+/// [`IfBroken`]: enum.Doc.html#variant.IfBroken
+///
+/// # Examples
+///
+/// Rendering deeply nested groups:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Sep, block, to_string};
+/// use pretty_trait::doc;
+///
+/// let mut nested: Box<dyn Pretty> = Box::new("z");
+/// for _ in 0..30 {
+///     nested = Box::new(Group::new("(".join(block("a".join(Sep(1)).join(nested))).join(")")));
+/// }
+///
+/// let lowered = nested.to_doc();
+/// let optimal = doc::to_string_optimal(&lowered, Some(80), 2);
+///
+/// assert_eq!(optimal, doc::to_string(&lowered, Some(80), 2));
+/// assert_eq!(optimal, to_string(&nested, Some(80), 2));
+/// ```
+///
+/// [`LineSuffix`]: enum.Doc.html#variant.LineSuffix
+pub fn write_optimal(
+    writer: &mut dyn io::Write,
+    doc: &Doc,
+    max_line: Option<usize>,
+    tab_size: usize,
+) -> io::Result<()> {
+    write_optimal_with_ribbon(writer, doc, max_line, None, tab_size)
+}
+
+/// Render a document to an arbitrary `io::Write` handle like [`write_optimal`], also counting any
+/// characters beyond the `ribbon` width on each line, not counting indentation, as overflow.
+///
+/// # Examples
+///
+/// ```
+/// use pretty_trait::doc::{self, Doc};
+///
+/// let call = Doc::Group(Box::new(Doc::Concat(vec![
+///     Doc::Text("foo(a,".to_string()),
+///     Doc::Line(1),
+///     Doc::Text("b)".to_string()),
+/// ])));
+/// let nested = Doc::Prefix("        ".to_string(), Box::new(Doc::Concat(vec![Doc::HardLine, call])));
+///
+/// assert_eq!(doc::to_string_optimal(&nested, Some(20), 4), "\n        foo(a, b)");
+/// assert_eq!(
+///     doc::to_string_optimal_with_ribbon(&nested, Some(20), Some(8), 4),
+///     doc::to_string_with_ribbon(&nested, Some(20), Some(8), 4)
+/// );
+/// ```
+///
+/// [`write_optimal`]: fn.write_optimal.html
+pub fn write_optimal_with_ribbon(
+    writer: &mut dyn io::Write,
+    doc: &Doc,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> io::Result<()> {
+    write_layout(writer, &best_layout(doc, max_line, ribbon, tab_size, None))
+}
+
+/// Find the best layout of a document, starting from the given decisions about labeled groups.
+fn best_layout<'a>(
+    doc: &'a Doc,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+    groups: Option<Rc<Decision>>,
+) -> State<'a> {
+    let start = State {
+        flat_suffixes: 0,
+        column: 0,
+        line_start: 0,
+        overflow: 0,
+        lines: 0,
+        line_breaks: 0,
+        fallbacks: 0,
+        output: None,
+        groups,
+        line_suffix: Vec::new(),
+    };
+    // The document as a whole is treated as a group.
+    let indent = Indentation::new();
+    let flat = vec![start.clone()];
+    let mut candidates = layouts(doc, &indent, Mode::Flat, flat, max_line, ribbon, tab_size);
+    candidates.extend(layouts(doc, &indent, Mode::Break, vec![start], max_line, ribbon, tab_size));
+    flush_line_suffix(candidates, ribbon, tab_size)
+        .into_iter()
+        .min_by_key(State::cost)
+        .expect("Every document has at least one layout")
+}
+
+/// Write the output of a layout found by `best_layout`.
+fn write_layout(writer: &mut dyn io::Write, layout: &State) -> io::Result<()> {
+    let mut pieces = Vec::new();
+    let mut output = layout.output.as_ref();
+    while let Some(node) = output {
+        pieces.push(&node.piece);
+        output = node.prev.as_ref();
+    }
+    let mut pending_indent = None;
+    for piece in pieces.into_iter().rev() {
+        match piece {
+            Piece::Text(text) => {
+                if !text.is_empty() {
+                    write_indent(writer, &mut pending_indent)?;
+                }
+                write!(writer, "{}", text)?;
+            }
+            Piece::Spaces(width) => {
+                if *width > 0 {
+                    write_indent(writer, &mut pending_indent)?;
+                }
+                for _ in 0..*width {
+                    write!(writer, " ")?;
+                }
+            }
+            Piece::Newline(ref indent) => {
+                write_line_break(writer, &mut pending_indent)?;
+                pending_indent = Some(indent.clone());
+            }
+            Piece::Indent(ref indent) => pending_indent = Some(indent.clone()),
+        }
+    }
+    write_indent(writer, &mut pending_indent)
+}
+
+/// Render a document to an owned string using [`write_optimal`], and return it.
+///
+/// [`write_optimal`]: fn.write_optimal.html
+pub fn to_string_optimal(doc: &Doc, max_line: Option<usize>, tab_size: usize) -> String {
+    to_string_optimal_with_ribbon(doc, max_line, None, tab_size)
+}
+
+/// Render a document to an owned string using [`write_optimal_with_ribbon`], and return it.
+///
+/// [`write_optimal_with_ribbon`]: fn.write_optimal_with_ribbon.html
+pub fn to_string_optimal_with_ribbon(
+    doc: &Doc,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> String {
+    let mut result = Vec::new();
+    write_optimal_with_ribbon(&mut result, doc, max_line, ribbon, tab_size)
+        .expect("Writing to a string should not fail");
+    String::from_utf8(result).expect("Invalid UTF8")
+}
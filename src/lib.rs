@@ -20,7 +20,7 @@
 //!     List(Vec<NestList>),
 //! }
 //!
-//! fn to_pretty(nest_list: &NestList) -> Box<Pretty> {
+//! fn to_pretty(nest_list: &NestList) -> Box<dyn Pretty> {
 //!     match nest_list {
 //!         &NestList::Atom(val) => Box::new(val.to_string()),
 //!         &NestList::List(ref children) => {
@@ -72,25 +72,289 @@
 //!
 //! [`Pretty`]: trait.Pretty.html
 
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::io;
+use std::mem;
 use std::ops::{Add, Mul, Deref};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use doc::Doc;
+
+pub mod doc;
 
 /// Represents the number of visual columns a value would take up if it were displayed on one line,
-/// unless it is inherently multi-line.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// or the widths of its lines if it contains unconditional line breaks, unless it is inherently
+/// multi-line.
+///
+/// Sizes are not ordered, since there is no single answer to whether a wide line is larger than a
+/// narrow block of lines.  Compare the widths of the variants directly instead.
+///
+/// # Examples
+///
+/// Combining the sizes of multi-line values:
+///
+/// ```
+/// use pretty_trait::{Lines, Size};
+///
+/// let lines = |first, widest, last| Size::Lines(Lines::new(first, widest, last));
+///
+/// assert_eq!(Size::Size(4) + Size::Size(1), Size::Size(5));
+/// assert_eq!(Size::Size(4) + lines(3, 10, 2), lines(7, 10, 2));
+/// assert_eq!(lines(3, 10, 2) + Size::Size(20), lines(3, 22, 22));
+/// assert_eq!(lines(3, 10, 2) + lines(3, 10, 2), lines(3, 10, 2));
+/// assert_eq!(lines(3, 10, 2) + Size::MultiLine, Size::MultiLine);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Size {
+    /// A value which is displayed on one line, taking up the given number of columns.
     Size(usize),
+
+    /// A value which contains line breaks which are rendered regardless of the environment, such as
+    /// [`Newline`]s.
+    ///
+    /// [`Newline`]: struct.Newline.html
+    Lines(Lines),
+
+    /// A value which is always displayed across multiple lines, and which should always cause its
+    /// environment to break.
     MultiLine,
 }
 
+/// The widths of the first, widest and last lines of a value which contains unconditional line
+/// breaks, as measured by [`Size::Lines`].
+///
+/// The widths of every line except the first are measured from the indentation of the environment
+/// the value appears in, from the column at which the value starts if it is inside an [`Align`],
+/// or from the start of the line if it is inside a [`Dedent`].  They include any indentation added inside the value, except for levels of
+/// [`Indent`], whose width depends on the tab size.  Those levels are recorded separately, so that
+/// a line which is not indented is not measured as if it were.
+///
+/// # Examples
+///
+/// Keeping a group flat when its widest line fits, even though a narrower line is indented:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Indent, Newline, Sep, doc, to_string};
+///
+/// let arg = "{".join(Indent(Newline.join("x"))).join(Newline).join("}_______________");
+/// let call = Group::new("f(".join(Sep(1)).join(arg).join(")"));
+///
+/// let expected = "\
+/// f( {
+///     x
+/// }_______________)";
+///
+/// assert_eq!(to_string(&call, Some(18), 4), expected);
+/// assert_eq!(doc::to_string(&call.to_doc(), Some(18), 4), expected);
+/// ```
+///
+/// [`Size::Lines`]: enum.Size.html#variant.Lines
+/// [`Align`]: struct.Align.html
+/// [`Dedent`]: struct.Dedent.html
+/// [`Indent`]: struct.Indent.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lines {
+    first: usize,
+    // The lines after the first, reduced to at most two lines which are together at least as wide
+    // and as deeply indented as each of them.
+    rest: [Line; 2],
+    last: Line,
+    start: Start,
+}
+
+/// Where the lines after the first of a value measured as `Lines` start.  Each start is at or
+/// after the one before it, so lines measured from a later start are never measured as narrower
+/// than they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Start {
+    // The start of the line, as inside a `Dedent`, whose indentation is included in the widths.
+    Fixed,
+    // The indentation of the environment.
+    Indent,
+    // The column at which the value starts, as inside an `Align`.
+    Column,
+}
+
+/// The width of a line, not counting the levels of `Indent` added to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Line {
+    width: usize,
+    depth: usize,
+}
+
+impl Line {
+    fn covers(self, other: Line) -> bool {
+        self.width >= other.width && self.depth >= other.depth
+    }
+
+    fn shifted(self, by: Line) -> Line {
+        Line {
+            width: self.width + by.width,
+            depth: self.depth + by.depth,
+        }
+    }
+}
+
+impl Lines {
+    /// The size of a block of lines with the given widths, none of which is indented.
+    pub fn new(first: usize, widest: usize, last: usize) -> Self {
+        let last = Line {
+            width: last,
+            depth: 0,
+        };
+        let widest = Line {
+            width: widest,
+            depth: 0,
+        };
+        Lines {
+            first,
+            rest: [last, last],
+            last,
+            start: Start::Indent,
+        }.with_line(widest)
+    }
+
+    /// The width of the first line.
+    pub fn first(&self) -> usize {
+        self.first
+    }
+
+    /// The width of the widest line other than the first, not counting any levels of `Indent`.
+    pub fn widest(&self) -> usize {
+        cmp::max(self.rest[0].width, self.rest[1].width)
+    }
+
+    /// The width of the last line, not counting any levels of `Indent`.
+    pub fn last(&self) -> usize {
+        self.last.width
+    }
+
+    /// The greatest number of levels of `Indent` added to any line.
+    fn depth(&self) -> usize {
+        cmp::max(self.rest[0].depth, self.rest[1].depth)
+    }
+
+    /// Record another line after the first.  If none of the lines covers another, the two most
+    /// deeply indented are merged into one which covers both.
+    fn with_line(mut self, line: Line) -> Lines {
+        let [a, b] = self.rest;
+        let mut rest = if a.covers(line) || b.covers(line) {
+            [a, b]
+        } else if line.covers(a) && line.covers(b) {
+            [line, line]
+        } else if line.covers(a) || b.covers(a) {
+            [b, line]
+        } else if line.covers(b) || a.covers(b) {
+            [a, line]
+        } else {
+            let mut lines = [a, b, line];
+            lines.sort_by_key(|line| line.depth);
+            let merged = Line {
+                width: cmp::max(lines[1].width, lines[2].width),
+                depth: lines[2].depth,
+            };
+            [lines[0], merged]
+        };
+        rest.sort_by_key(|line| (line.width, line.depth));
+        self.rest = rest;
+        self
+    }
+
+    /// Move every line after the first by the given width and number of levels of `Indent`.
+    fn shifted(mut self, by: Line) -> Lines {
+        self.rest = [self.rest[0].shifted(by), self.rest[1].shifted(by)];
+        self.last = self.last.shifted(by);
+        self
+    }
+}
+
 impl Size {
+    /// Check whether a value of this size, starting at the beginning of a line, would exceed the
+    /// maximum line length.  Lines after the first are assumed to have no indentation.
     fn exceeds(self, max_line: Option<usize>) -> bool {
-        self >
-            match max_line {
-                Some(max) => Size::Size(max),
-                None => Size::MultiLine,
+        match (self.width(), max_line) {
+            (Some(width), Some(max)) => width > max,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        }
+    }
+
+    /// Whether or not a value of this size spans multiple lines.
+    fn is_multi_line(self) -> bool {
+        match self {
+            Size::Size(_) => false,
+            Size::Lines(_) | Size::MultiLine => true,
+        }
+    }
+
+    /// The width of the widest line of a value of this size, not counting any levels of `Indent`,
+    /// or `None` if it is inherently multi-line.
+    fn width(self) -> Option<usize> {
+        match self {
+            Size::Size(size) => Some(size),
+            Size::Lines(lines) => Some(cmp::max(lines.first, lines.widest())),
+            Size::MultiLine => None,
+        }
+    }
+
+    /// Whichever of two sizes has the narrowest widest line, preferring fewer levels of `Indent`
+    /// and then a single line between sizes which are equally wide, and `self` between sizes which
+    /// are otherwise equal.
+    fn narrowest(self, other: Size) -> Size {
+        let key = |size: Size| {
+            let depth = match size {
+                Size::Lines(lines) => lines.depth(),
+                _ => 0,
+            };
+            (size.width().is_none(), size.width(), depth, size.is_multi_line())
+        };
+        if key(other) < key(self) { other } else { self }
+    }
+
+    /// The size of a value of this size whose lines after the first are further indented by the
+    /// given number of levels of `Indent` and the given number of columns.
+    fn indented(self, levels: usize, width: usize) -> Size {
+        match self {
+            Size::Lines(lines) if lines.start != Start::Fixed => {
+                Size::Lines(lines.shifted(Line {
+                    width,
+                    depth: levels,
+                }))
+            }
+            _ => self,
+        }
+    }
+
+    /// The size of a value of this size whose lines after the first are indented to the given
+    /// number of columns past the column at which it starts.
+    fn aligned(self, offset: usize) -> Size {
+        match self {
+            Size::Lines(lines) if lines.start != Start::Fixed => {
+                let mut lines = lines.shifted(Line {
+                    width: offset,
+                    depth: 0,
+                });
+                lines.start = Start::Column;
+                Size::Lines(lines)
+            }
+            _ => self,
+        }
+    }
+
+    /// The size of a value of this size whose lines after the first are indented to the given
+    /// fixed column, regardless of its environment.
+    fn dedented(self, width: usize) -> Size {
+        match self {
+            Size::Lines(lines) if lines.start == Start::Indent => {
+                let mut lines = lines.shifted(Line { width, depth: 0 });
+                lines.start = Start::Fixed;
+                Size::Lines(lines)
             }
+            _ => self,
+        }
     }
 }
 
@@ -100,6 +364,41 @@ impl Add<Size> for Size {
     fn add(self, other: Size) -> Size {
         match (self, other) {
             (Size::Size(size1), Size::Size(size2)) => Size::Size(size1 + size2),
+            (Size::Size(size), Size::Lines(mut lines)) => {
+                lines.first += size;
+                if lines.start == Start::Column {
+                    // Aligned lines move along with the start of the value.
+                    lines = lines.shifted(Line {
+                        width: size,
+                        depth: 0,
+                    });
+                }
+                Size::Lines(lines)
+            }
+            (Size::Lines(mut lines), Size::Size(size)) => {
+                lines.last.width += size;
+                let last = lines.last;
+                Size::Lines(lines.with_line(last))
+            }
+            (Size::Lines(lines1), Size::Lines(mut lines2)) => {
+                let joined = Line {
+                    width: lines1.last.width + lines2.first,
+                    depth: lines1.last.depth,
+                };
+                if lines2.start == Start::Column {
+                    // The start of the second value is on the last line of the first.
+                    lines2 = lines2.shifted(lines1.last);
+                    lines2.start = lines1.start;
+                }
+                let mut lines = lines1.with_line(joined);
+                lines = lines.with_line(lines2.rest[0]).with_line(lines2.rest[1]);
+                lines.last = lines2.last;
+                // Lines measured from different starts are all measured from the later one, such as
+                // the start of an aligned value, which is never before the indentation of the
+                // environment.
+                lines.start = cmp::max(lines1.start, lines2.start);
+                Size::Lines(lines)
+            }
             _ => Size::MultiLine,
         }
     }
@@ -111,11 +410,278 @@ impl Mul<usize> for Size {
     fn mul(self, other: usize) -> Size {
         match self {
             Size::Size(size) => Size::Size(size * other),
+            Size::Lines(lines) => {
+                match other {
+                    0 => Size::Size(0),
+                    1 => self,
+                    // Every join between repetitions puts the last line of one on the same line as
+                    // the first line of the next, and the lines of each aligned repetition are
+                    // further along than the last.
+                    _ if lines.start == Start::Column => (1..other).fold(self, |total, _| total + self),
+                    _ => self + self,
+                }
+            }
             Size::MultiLine => Size::MultiLine,
         }
     }
 }
 
+/// An output handle which keeps track of the column at which the next character will be written.
+///
+/// All rendering goes through a `Writer`, so that layout decisions can take into account any text
+/// which has already been written on the current line.  It implements `io::Write`, so custom
+/// `Pretty` types can write to it directly with the `write!` macro.
+pub struct Writer<'a> {
+    inner: &'a mut dyn io::Write,
+    column: usize,
+    widest: usize,
+    // The column at which the text after the indentation of the current line starts, and the
+    // widest such text written so far, for checking the ribbon width.
+    line_start: usize,
+    widest_ribbon: usize,
+    groups: HashMap<GroupId, bool>,
+    line_suffix: Vec<u8>,
+    // The indentation following each line break which has been requested but not yet written.
+    // These are written lazily, so that blank lines carry no trailing whitespace.
+    pending_lines: Vec<Indentation>,
+    // The lowest priority of any separator left unbroken by the innermost prioritized group.
+    skipped_priority: Option<usize>,
+    // The choices of the prioritized groups rendered so far directly inside the innermost
+    // enclosing prioritized group, which are only recorded in a trial render, and those made in the
+    // last trial render of that group, which are reused rather than tried again.
+    chosen_priorities: Option<Vec<Choice>>,
+    replayed_priorities: VecDeque<Choice>,
+}
+
+/// The priority chosen by a prioritized group, and the choices of the prioritized groups inside it.
+#[derive(Clone, Debug)]
+struct Choice {
+    priority: usize,
+    nested: Vec<Choice>,
+}
+
+impl<'a> Writer<'a> {
+    /// Wrap an `io::Write` handle, assuming that output starts at the beginning of a line.
+    pub fn new(inner: &'a mut dyn io::Write) -> Self {
+        Writer {
+            inner,
+            column: 0,
+            widest: 0,
+            line_start: 0,
+            widest_ribbon: 0,
+            groups: HashMap::new(),
+            line_suffix: Vec::new(),
+            pending_lines: Vec::new(),
+            skipped_priority: None,
+            chosen_priorities: None,
+            replayed_priorities: VecDeque::new(),
+        }
+    }
+
+    /// The number of visual columns which have been written on the current line so far.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Whether the group with the given ID was broken, or `None` if it has not been rendered yet.
+    pub fn group_broken(&self, id: GroupId) -> Option<bool> {
+        self.groups.get(&id).cloned()
+    }
+
+    /// Write formatted text.  This allows the `write!` macro to be used on a `Writer` without
+    /// importing `io::Write`.
+    pub fn write_fmt(&mut self, args: fmt::Arguments) -> io::Result<()> {
+        io::Write::write_fmt(self, args)
+    }
+
+    /// Buffer text to be written at the end of the current line, just before the next newline.
+    pub fn push_line_suffix(&mut self, suffix: &[u8]) -> io::Result<()> {
+        if !suffix.is_empty() {
+            // The current line starts once any pending line breaks are written, so the suffix is
+            // not carried past the next of them.
+            self.write_pending_lines()?;
+        }
+        self.line_suffix.extend_from_slice(suffix);
+        Ok(())
+    }
+
+    /// Write any pending line breaks and any text buffered by [`push_line_suffix`].  This must be
+    /// called once all other output has been written.
+    ///
+    /// [`push_line_suffix`]: #method.push_line_suffix
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.write_pending_lines()?;
+        self.write_line_suffix()
+    }
+
+    fn write_line_suffix(&mut self) -> io::Result<()> {
+        let suffix = mem::take(&mut self.line_suffix);
+        self.write_raw(&suffix)
+    }
+
+    /// Request `count` line breaks followed by `indent`, to be written before the next text.  If
+    /// `collapse` is set, this is merged with any line breaks already pending so that only the
+    /// larger number is written, and otherwise the numbers are added.
+    fn break_lines(
+        &mut self,
+        count: usize,
+        collapse: bool,
+        indent: &Indentation,
+    ) -> io::Result<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        if self.pending_lines.is_empty() {
+            // Any line suffix belongs at the end of the line being broken.
+            self.write_line_suffix()?;
+        }
+        let total = if collapse {
+            cmp::max(self.pending_lines.len(), count)
+        } else {
+            self.pending_lines.len() + count
+        };
+        // Blank lines keep the indentation of the line breaks which started them, but the line
+        // after the last break is indented by this one, even if it was merged with an earlier one.
+        if self.pending_lines.len() == total {
+            self.pending_lines.pop();
+        }
+        while self.pending_lines.len() < total {
+            self.pending_lines.push(indent.clone());
+        }
+        self.column = indent.width();
+        self.line_start = self.column;
+        self.widest = cmp::max(self.widest, self.column);
+        Ok(())
+    }
+
+    fn write_pending_lines(&mut self) -> io::Result<()> {
+        let pending_lines = mem::take(&mut self.pending_lines);
+        for (i, indent) in pending_lines.iter().enumerate() {
+            if i > 0 {
+                // A blank line keeps any prefix in the indentation, such as `//`.
+                let blank = &pending_lines[i - 1];
+                self.inner.write_all(blank.as_str().trim_end().as_bytes())?;
+            }
+            self.inner.write_all(b"\n")?;
+            if i + 1 == pending_lines.len() {
+                // The column has already been updated to account for the indentation.
+                self.inner.write_all(indent.as_str().as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        for &byte in bytes {
+            if byte == b'\n' {
+                self.column = 0;
+                self.line_start = 0;
+            } else if byte & 0xC0 != 0x80 {
+                // Count each UTF-8 encoded character once, by skipping continuation bytes.
+                self.column += 1;
+                self.widest = cmp::max(self.widest, self.column);
+                self.widest_ribbon = cmp::max(self.widest_ribbon, self.column - self.line_start);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> io::Write for Writer<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.write_pending_lines()?;
+        }
+        let mut rest = buf;
+        while let Some(newline) = rest.iter().position(|&byte| byte == b'\n') {
+            self.write_raw(&rest[..newline])?;
+            self.write_line_suffix()?;
+            self.write_raw(b"\n")?;
+            rest = &rest[newline + 1..];
+        }
+        self.write_raw(rest)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The text to be inserted at the start of each new line, made up of spaces and arbitrary prefix
+/// strings.
+///
+/// You only need to use this struct if you are implementing your own `Pretty` types.  To indent
+/// content, use one of the [`Indent`], [`IndentBy`], [`Align`], [`Hang`], [`Prefix`] or [`Dedent`]
+/// wrappers.
+///
+/// [`Indent`]: struct.Indent.html
+/// [`IndentBy`]: struct.IndentBy.html
+/// [`Align`]: struct.Align.html
+/// [`Hang`]: struct.Hang.html
+/// [`Prefix`]: struct.Prefix.html
+/// [`Dedent`]: struct.Dedent.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Indentation {
+    text: Rc<String>,
+    width: usize,
+}
+
+impl Indentation {
+    /// No indentation at all.
+    pub fn new() -> Self {
+        Indentation::default()
+    }
+
+    /// An indentation of the given number of spaces.
+    pub fn spaces(width: usize) -> Self {
+        Indentation::new().with_spaces(width)
+    }
+
+    /// The number of visual columns taken up by this indentation.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The text of this indentation.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Extend this indentation by the given number of spaces.
+    pub fn with_spaces(&self, width: usize) -> Self {
+        let mut text = String::with_capacity(self.text.len() + width);
+        text.push_str(&self.text);
+        text.extend((0..width).map(|_| ' '));
+        Indentation {
+            text: Rc::new(text),
+            width: self.width + width,
+        }
+    }
+
+    /// Extend this indentation by an arbitrary prefix string.
+    pub fn with_prefix(&self, prefix: &str) -> Self {
+        Indentation {
+            text: Rc::new(format!("{}{}", self.text, prefix)),
+            width: self.width + prefix.chars().count(),
+        }
+    }
+
+    /// Extend this indentation with spaces up to the given column, or truncate it if it is already
+    /// wider than that.
+    pub fn aligned_to(&self, column: usize) -> Self {
+        if column >= self.width {
+            self.with_spaces(column - self.width)
+        } else {
+            Indentation {
+                text: Rc::new(self.text.chars().take(column).collect()),
+                width: column,
+            }
+        }
+    }
+}
+
 /// A struct used internally in pretty-printing to store information about the rendering
 /// environment.
 ///
@@ -125,34 +691,212 @@ impl Mul<usize> for Size {
 ///
 /// [`write`]: fn.write.html
 /// [`to_string`]: fn.to_string.html
-pub struct Context<'a> {
+pub struct Context<'a, 'w: 'a> {
     /// The maximum desired line length, or `None` if lines may be of unlimited length.
     pub max_line: Option<usize>,
 
     /// The desired number of spaces to use for a single level of indentation.
     pub tab_size: usize,
 
-    /// The current number of tab stops to be inserted before each new line.
+    /// The number of [`Indent`] wrappers enclosing the value being rendered.
+    ///
+    /// [`Indent`]: struct.Indent.html
     pub indent_level: usize,
 
+    /// The indentation to be inserted after each new line.  This is `tab_size * indent_level`
+    /// spaces, unless it has been changed by a wrapper such as [`Align`] or [`Prefix`].
+    ///
+    /// [`Align`]: struct.Align.html
+    /// [`Prefix`]: struct.Prefix.html
+    pub indent: Indentation,
+
     /// Whether or not the environment has been broken across multiple lines because its contents
     /// were too large.
     pub broken: bool,
 
-    /// The handle to render to.
-    pub writer: &'a mut io::Write,
+    /// Whether or not separators in a broken environment should only break when the text following
+    /// them would not otherwise fit, as in a group created with [`Group::inconsistent`].
+    ///
+    /// [`Group::inconsistent`]: struct.Group.html#method.inconsistent
+    pub inconsistent: bool,
+
+    /// The maximum desired number of characters on each line, not counting indentation, or `None`
+    /// if only `max_line` applies.
+    pub ribbon: Option<usize>,
+
+    /// The highest priority of [`PrioritySep`] which breaks in a broken environment, as chosen by a
+    /// group created with [`Group::prioritized`], or `None` if every separator breaks.
+    ///
+    /// [`PrioritySep`]: struct.PrioritySep.html
+    /// [`Group::prioritized`]: struct.Group.html#method.prioritized
+    pub priority: Option<usize>,
+
+    /// The size of the text which will immediately follow the value being rendered, up to the next
+    /// possible line break.  This text will end up on the same line as the end of the value, so it
+    /// is taken into account when deciding whether or not a `Group` fits.
+    pub trailing: Size,
+
+    /// The handle to render to, which also tracks the current column.
+    pub writer: &'a mut Writer<'w>,
 }
 
-impl<'a> Context<'a> {
-    fn reborrow<'b>(&'b mut self) -> Context<'b> {
+impl<'a, 'w> Context<'a, 'w> {
+    fn reborrow(&mut self) -> Context<'_, 'w> {
+        Context {
+            max_line: self.max_line,
+            tab_size: self.tab_size,
+            indent_level: self.indent_level,
+            indent: self.indent.clone(),
+            broken: self.broken,
+            inconsistent: self.inconsistent,
+            ribbon: self.ribbon,
+            priority: self.priority,
+            trailing: self.trailing,
+            writer: self.writer,
+        }
+    }
+
+    fn followed_by<T: Pretty + ?Sized>(&mut self, next: &T) -> Context<'_, 'w> {
+        let trailing = leading_size(next, self.trailing, self.broken);
+        let mut context = self.reborrow();
+        context.trailing = trailing;
+        context
+    }
+
+    /// Check whether a value of the given size, followed by the trailing text, would overflow the
+    /// current line.
+    fn overflows(&self, size: Size) -> bool {
+        self.exceeds_line(size + self.trailing)
+    }
+
+    /// Check whether a value of the given size would overflow the current line, either by exceeding
+    /// `max_line` or by exceeding the ribbon width.
+    fn exceeds_line(&self, size: Size) -> bool {
+        let ribbon_used = self.writer.column.saturating_sub(self.writer.line_start);
+        let exceeds_first = |first: Size| {
+            (Size::Size(self.writer.column) + first).exceeds(self.max_line) ||
+                (Size::Size(ribbon_used) + first).exceeds(self.ribbon)
+        };
+        match size {
+            // Every line after the first starts at the current indentation, at the current column
+            // if it is aligned, or at the start of the line if it is dedented, plus any levels of
+            // indentation added inside the value.
+            Size::Lines(lines) => {
+                let start = match lines.start {
+                    Start::Fixed => 0,
+                    Start::Indent => self.indent.width(),
+                    Start::Column => self.writer.column,
+                };
+                exceeds_first(Size::Size(lines.first)) ||
+                    lines.rest.iter().any(|line| {
+                        let indent = start + line.depth * self.tab_size;
+                        Size::Size(indent + line.width).exceeds(self.max_line) ||
+                            Size::Size(line.width).exceeds(self.ribbon)
+                    })
+            }
+            _ => exceeds_first(size),
+        }
+    }
+
+    /// Check whether a separator of the given size should break in this context.
+    fn breaks(&self, size: Size) -> bool {
+        self.broken && (!self.inconsistent || self.overflows(size))
+    }
+
+    /// Check whether rendering `content` in this context would keep every line within `max_line`,
+    /// including any trailing text after it, without writing anything.  Also return the lowest
+    /// priority of any separator which was left unbroken because of `priority`, and the choices of
+    /// the prioritized groups inside `content`.
+    fn trial<T: Pretty + ?Sized>(&self, content: &T) -> (bool, Option<usize>, Vec<Choice>) {
+        if self.max_line.is_none() && self.ribbon.is_none() {
+            return (true, None, Vec::new());
+        }
+        let mut sink = io::sink();
+        let mut writer = Writer {
+            inner: &mut sink,
+            column: self.writer.column,
+            widest: self.writer.column,
+            line_start: self.writer.line_start,
+            widest_ribbon: self.writer.column.saturating_sub(self.writer.line_start),
+            groups: self.writer.groups.clone(),
+            line_suffix: self.writer.line_suffix.clone(),
+            pending_lines: self.writer.pending_lines.clone(),
+            skipped_priority: None,
+            chosen_priorities: Some(Vec::new()),
+            replayed_priorities: VecDeque::new(),
+        };
+        let fits = {
+            let mut context = self.with_writer(&mut writer);
+            content.pretty_write(context.reborrow()).is_ok() &&
+                !context.exceeds_line(context.trailing)
+        };
+        let fits = fits && !Size::Size(writer.widest).exceeds(self.max_line) &&
+            !Size::Size(writer.widest_ribbon).exceeds(self.ribbon);
+        let chosen = writer.chosen_priorities.unwrap_or_default();
+        (fits, writer.skipped_priority, chosen)
+    }
+
+    /// Render `content` on its own, as a block of lines starting at column 0, with the given
+    /// maximum line length.
+    fn render_block<T: Pretty + ?Sized>(
+        &self,
+        content: &T,
+        max_line: Option<usize>,
+    ) -> io::Result<Vec<String>> {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = Writer::new(&mut buffer);
+            writer.groups = self.writer.groups.clone();
+            let mut context = self.with_writer(&mut writer);
+            context.max_line = max_line;
+            context.indent_level = 0;
+            context.indent = Indentation::new();
+            context.inconsistent = false;
+            context.priority = None;
+            context.trailing = Size::Size(0);
+            context.broken = context.overflows(content.size());
+            content.pretty_write(context)?;
+            writer.finish()?;
+        }
+        let text = String::from_utf8(buffer)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(text.split('\n').map(str::to_string).collect())
+    }
+
+    /// Create a copy of this context which renders to a different writer.
+    fn with_writer<'b, 'v>(&self, writer: &'b mut Writer<'v>) -> Context<'b, 'v> {
         Context {
             max_line: self.max_line,
             tab_size: self.tab_size,
             indent_level: self.indent_level,
+            indent: self.indent.clone(),
             broken: self.broken,
-            writer: &mut self.writer,
+            inconsistent: self.inconsistent,
+            ribbon: self.ribbon,
+            priority: self.priority,
+            trailing: self.trailing,
+            writer,
         }
     }
+
+    /// Start a new line, indented by the current indentation.
+    ///
+    /// The line break is only written along with the text which follows it, so that blank lines
+    /// carry no trailing whitespace.
+    pub fn newline(&mut self) -> io::Result<()> {
+        self.writer.break_lines(1, false, &self.indent)
+    }
+
+    /// Start a new line after the given number of line breaks, indented by the current
+    /// indentation.
+    ///
+    /// Unlike repeated calls to [`newline`], consecutive calls to `break_lines` with no text in
+    /// between are merged, so that only the largest number of line breaks requested is written.
+    ///
+    /// [`newline`]: #method.newline
+    pub fn break_lines(&mut self, count: usize) -> io::Result<()> {
+        self.writer.break_lines(count, true, &self.indent)
+    }
 }
 
 /// Types which can be pretty-printed.
@@ -160,10 +904,34 @@ impl<'a> Context<'a> {
 /// Strings implement `Pretty`, as do a number of useful built-in composable wrapper types.  As
 /// such, you usually don't need to implement it for your own types, although you can if necessary.
 ///
+/// A string which contains newlines is measured as [`Size::Lines`], and each of its lines after
+/// the first is written on a new line at the current indentation, just as if the lines were joined
+/// by [`Newline`]s:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Indent, Newline, to_string};
+///
+/// let snippet = "if x {\n    y\n}";
+///
+/// let expected = "\
+/// fn f() {
+///     if x {
+///         y
+///     }
+/// }";
+///
+/// assert_eq!(
+///     to_string(&"fn f() {".join(Indent(Newline.join(snippet))).join(Newline).join("}"), None, 4),
+///     expected
+/// );
+/// ```
+///
 /// You usually do not need to directly call the methods defined here, unless your are implementing
 /// your own `Pretty` type.  If you just want to render a value to a buffer or an IO handle, use one
 /// of the [`write`], [`println_simple`], or [`to_string`] functions instead.
 ///
+/// [`Size::Lines`]: enum.Size.html#variant.Lines
+/// [`Newline`]: struct.Newline.html
 /// [`write`]: fn.write.html
 /// [`println_simple`]: fn.println_simple.html
 /// [`to_string`]: fn.to_string.html
@@ -171,67 +939,256 @@ pub trait Pretty {
     /// Calculate the intrinsic size of this value, if it were to be displayed on a single line.
     fn size(&self) -> Size;
 
+    /// Calculate the size of this value if it were to be displayed on a single line inside a
+    /// broken environment.
+    ///
+    /// This is used to measure the text following a value, which ends up on the same line as it.
+    /// The default implementation returns [`size`], which is correct for any value whose contents
+    /// do not depend on whether or not its environment is broken.  It differs for values such as
+    /// [`Conditional`].
+    ///
+    /// [`size`]: #tymethod.size
+    /// [`Conditional`]: enum.Conditional.html
+    fn broken_size(&self) -> Size {
+        self.size()
+    }
+
+    /// Calculate the size of the text at the start of this value which comes before its first
+    /// possible line break, in an environment which is broken or not, or `None` if this value
+    /// contains no possible line breaks in that environment.
+    ///
+    /// A separator can only break in a broken environment, so in an environment which is not
+    /// broken the prefix runs through the separator's flat text.  A nested [`Group`] decides for
+    /// itself, so its separators count as possible line breaks either way.
+    ///
+    /// The default implementation returns `None`, which is correct for any value which never
+    /// breaks.
+    ///
+    /// [`Group`]: struct.Group.html
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        None
+    }
+
     /// Render this value in a given context.
     fn pretty_write(&self, context: Context) -> io::Result<()>;
+
+    /// Lower this value to the document representation used by the [`doc`] module.
+    ///
+    /// The default implementation renders this value with no maximum line length and treats the
+    /// result as literal text, which is correct for any value which never breaks.  The levels of
+    /// indentation added to each line by [`Indent`] are kept, and rendered with the tab size given
+    /// to the [`doc`] renderer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pretty_trait::{JoinExt, Context, Indent, Newline, Pretty, Size, doc, to_string};
+    /// use std::io;
+    ///
+    /// struct Block;
+    ///
+    /// impl Pretty for Block {
+    ///     fn size(&self) -> Size {
+    ///         Size::MultiLine
+    ///     }
+    ///
+    ///     fn pretty_write(&self, context: Context) -> io::Result<()> {
+    ///         "{".join(Indent(Newline.join("x"))).join(Newline).join("}").pretty_write(context)
+    ///     }
+    /// }
+    ///
+    /// let item = "fn f() ".join(Block);
+    ///
+    /// assert_eq!(to_string(&item, None, 4), "fn f() {\n    x\n}");
+    /// assert_eq!(doc::to_string(&item.to_doc(), None, 4), "fn f() {\n    x\n}");
+    /// ```
+    ///
+    /// [`doc`]: doc/index.html
+    /// [`Indent`]: struct.Indent.html
+    fn to_doc(&self) -> Doc {
+        // Each level of indentation takes up one column more in the second rendering than in the
+        // first, so the difference gives the number of levels on each line.
+        indented_lines(&to_string(self, None, 0), &to_string(self, None, 1))
+    }
+}
+
+/// Lower text rendered with a tab size of zero to a `Doc`, nesting each line by the number of levels
+/// of indentation found by comparing it with the same text rendered with a tab size of one.
+fn indented_lines(text: &str, indented: &str) -> Doc {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let indented: Vec<&str> = indented.split('\n').collect();
+    if lines.len() != indented.len() {
+        return Doc::lines(text);
+    }
+    let mut docs = Vec::new();
+    for (i, (line, indented)) in lines.iter().zip(&indented).enumerate() {
+        let text = if line.is_empty() {
+            Doc::Nil
+        } else {
+            Doc::Text(line.to_string())
+        };
+        if i == 0 {
+            docs.push(text);
+            continue;
+        }
+        let levels = indented.chars().count().saturating_sub(line.chars().count());
+        let line = Doc::Concat(vec![Doc::HardLine, text]);
+        docs.push(if levels > 0 {
+            Doc::Nest(levels, Box::new(line))
+        } else {
+            line
+        });
+    }
+    match docs.len() {
+        1 => docs.pop().unwrap(),
+        _ => Doc::Concat(docs),
+    }
 }
 
-impl<'a, T: Pretty + ?Sized> Pretty for &'a T {
+impl<T: Pretty + ?Sized> Pretty for &T {
     fn size(&self) -> Size {
         (*self).size()
     }
 
-    fn pretty_write(&self, context: Context) -> io::Result<()> {
-        (*self).pretty_write(context)
+    fn broken_size(&self) -> Size {
+        (*self).broken_size()
     }
-}
 
-impl<'a, T: Pretty + ?Sized> Pretty for &'a mut T {
-    fn size(&self) -> Size {
-        (**self).size()
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        (*self).break_prefix(broken)
+    }
+
+    fn pretty_write(&self, context: Context) -> io::Result<()> {
+        (*self).pretty_write(context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        (*self).to_doc()
+    }
+}
+
+impl<T: Pretty + ?Sized> Pretty for &mut T {
+    fn size(&self) -> Size {
+        (**self).size()
+    }
+
+    fn broken_size(&self) -> Size {
+        (**self).broken_size()
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        (**self).break_prefix(broken)
     }
 
     fn pretty_write(&self, context: Context) -> io::Result<()> {
         (**self).pretty_write(context)
     }
+
+    fn to_doc(&self) -> Doc {
+        (**self).to_doc()
+    }
 }
 
-impl<'a, T: Pretty + ?Sized> Pretty for Box<T> {
+impl<T: Pretty + ?Sized> Pretty for Box<T> {
     fn size(&self) -> Size {
         self.deref().size()
     }
 
+    fn broken_size(&self) -> Size {
+        self.deref().broken_size()
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.deref().break_prefix(broken)
+    }
+
     fn pretty_write(&self, context: Context) -> io::Result<()> {
         self.deref().pretty_write(context)
     }
+
+    fn to_doc(&self) -> Doc {
+        self.deref().to_doc()
+    }
 }
 
-impl<'a, T: Pretty + ?Sized> Pretty for Rc<T> {
+impl<T: Pretty + ?Sized> Pretty for Rc<T> {
     fn size(&self) -> Size {
         self.deref().size()
     }
 
+    fn broken_size(&self) -> Size {
+        self.deref().broken_size()
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.deref().break_prefix(broken)
+    }
+
     fn pretty_write(&self, context: Context) -> io::Result<()> {
         self.deref().pretty_write(context)
     }
+
+    fn to_doc(&self) -> Doc {
+        self.deref().to_doc()
+    }
+}
+
+fn text_size(text: &str) -> Size {
+    let mut lines = text.split('\n').map(|line| line.chars().count());
+    let first = lines.next().unwrap_or(0);
+    lines.fold(Size::Size(first), |total, line| {
+        total + Size::Lines(Lines::new(0, line, line))
+    })
+}
+
+fn text_break_prefix(text: &str) -> Option<Size> {
+    text.find('\n')
+        .map(|newline| Size::Size(text[..newline].chars().count()))
+}
+
+fn write_text(text: &str, mut context: Context) -> io::Result<()> {
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            context.newline()?;
+        }
+        write!(context.writer, "{}", line)?;
+    }
+    Ok(())
 }
 
-impl<'a> Pretty for &'a str {
+impl Pretty for &str {
     fn size(&self) -> Size {
-        Size::Size(self.chars().count())
+        text_size(self)
+    }
+
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        text_break_prefix(self)
     }
 
     fn pretty_write(&self, context: Context) -> io::Result<()> {
-        write!(context.writer, "{}", self)
+        write_text(self, context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::lines(self)
     }
 }
 
 impl Pretty for String {
     fn size(&self) -> Size {
-        Size::Size(self.chars().count())
+        text_size(self)
+    }
+
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        text_break_prefix(self)
     }
 
     fn pretty_write(&self, context: Context) -> io::Result<()> {
-        write!(context.writer, "{}", self)
+        write_text(self, context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::lines(self)
     }
 }
 
@@ -282,19 +1239,209 @@ impl Pretty for String {
 ///     expected_grouped,
 /// );
 /// ```
+///
+/// Text already written on the current line counts toward the line length:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Sep, to_string};
+///
+/// let max_line = Some(16);
+/// let tab_size = 4;
+///
+/// let expected = "\
+/// let x = hello
+/// world";
+///
+/// assert_eq!(
+///     to_string(
+///         &"let x = ".join(Group::new("hello".join(Sep(1)).join("world"))),
+///         max_line,
+///         tab_size,
+///     ),
+///     expected
+/// );
+/// ```
+///
+/// So does any text which immediately follows the group, up to the next possible line break:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Sep, delimited, block, to_string};
+///
+/// let max_line = Some(9);
+/// let tab_size = 4;
+///
+/// let call = Group::new("foo(".join(block(delimited(&",".join(Sep(1)), &["a", "b"]))).join(")"));
+///
+/// // "foo(a, b)" is exactly 9 columns wide, but the following semicolon would not fit:
+///
+/// let expected = "\
+/// foo(
+///     a,
+///     b
+/// );";
+///
+/// assert_eq!(to_string(&call.join(";"), max_line, tab_size), expected);
+/// ```
+///
+/// # Break modes
+///
+/// A group created with [`Group::new`] breaks *consistently*: if it does not fit, every separator
+/// directly inside it breaks.  The other constructors choose a different strategy.  In every case,
+/// the mode of a group only affects the separators and [`Conditional`]s directly inside it, and
+/// never those inside a nested group, which makes its own decision.
+///
+/// A group created with [`Group::inconsistent`] breaks only those separators which are followed by
+/// text that would not otherwise fit on the current line:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Sep, to_string};
+///
+/// let words = Group::inconsistent(
+///     "lorem".join(Sep(1)).join("ipsum").join(Sep(1)).join("dolor").join(Sep(1)).join("sit"),
+/// );
+///
+/// assert_eq!(to_string(&words, Some(12), 4), "lorem ipsum\ndolor sit");
+/// ```
+///
+/// A group created with [`Group::always_broken`] breaks even when it fits.  Because it is always
+/// rendered across multiple lines, it also causes its environment to break:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Sep, to_string};
+///
+/// let body = Group::always_broken("{".join(Sep(1)).join("x").join(Sep(1)).join("}"));
+///
+/// assert_eq!(to_string(&body, None, 4), "{\nx\n}");
+/// ```
+///
+/// A group created with [`Group::never_broken`] never breaks, even when it is inside a broken
+/// environment:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Sep, to_string};
+///
+/// fn field(key: &'static str, value: &'static str) -> impl Pretty {
+///     Group::never_broken(key.join(":").join(Sep(1)).join(value))
+/// }
+///
+/// let fields = Group::new(field("alpha", "1").join(",").join(Sep(1)).join(field("beta", "2")));
+///
+/// assert_eq!(to_string(&fields, Some(10), 4), "alpha: 1,\nbeta: 2");
+/// ```
+///
+/// A group nested inside it still breaks if it does not fit, when rendered either directly or
+/// through the [`doc`] module:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Sep, doc, to_string};
+///
+/// let labeled = Group::never_broken("x ".join(Group::new("aaaa".join(Sep(1)).join("bbbb"))));
+///
+/// assert_eq!(to_string(&labeled, Some(6), 4), "x aaaa\nbbbb");
+/// assert_eq!(doc::to_string(&labeled.to_doc(), Some(6), 4), "x aaaa\nbbbb");
+/// ```
+///
+/// A group created with [`Group::prioritized`] breaks its [`PrioritySep`]s in order of priority,
+/// starting with the lowest, and only breaks higher priority separators if the lines still do not
+/// fit:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, PrioritySep, doc, to_string, to_string_optimal};
+///
+/// let condition = Group::prioritized(
+///     "aaaa"
+///         .join(PrioritySep(1, 1))
+///         .join("&& bbbb")
+///         .join(PrioritySep(0, 1))
+///         .join("|| cccc"),
+/// );
+///
+/// assert_eq!(to_string(&condition, Some(14), 4), "aaaa && bbbb\n|| cccc");
+/// assert_eq!(to_string(&condition, Some(10), 4), "aaaa\n&& bbbb\n|| cccc");
+///
+/// // The doc renderers see the priorities as nested groups:
+///
+/// assert_eq!(doc::to_string(&condition.to_doc(), Some(14), 4), "aaaa && bbbb\n|| cccc");
+/// assert_eq!(to_string_optimal(&condition, Some(14), 4), "aaaa && bbbb\n|| cccc");
+/// ```
+///
+/// [`Group::new`]: #method.new
+/// [`Group::inconsistent`]: #method.inconsistent
+/// [`Group::always_broken`]: #method.always_broken
+/// [`Group::never_broken`]: #method.never_broken
+/// [`Group::prioritized`]: #method.prioritized
+/// [`PrioritySep`]: struct.PrioritySep.html
+/// [`Conditional`]: enum.Conditional.html
+/// [`doc`]: doc/index.html
 #[derive(Clone, Copy, Debug)]
 pub struct Group<T> {
     size: Size,
+    mode: BreakMode,
+    id: Option<GroupId>,
     content: T,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BreakMode {
+    Consistent,
+    Inconsistent,
+    Always,
+    Never,
+    Prioritized,
+}
+
 impl<T: Pretty> Group<T> {
+    /// Group content so that every separator directly inside it breaks if it does not fit.
     pub fn new(content: T) -> Self {
+        Group::with_mode(BreakMode::Consistent, content)
+    }
+
+    /// Group content so that each separator directly inside it breaks only if the text following
+    /// it would not otherwise fit.
+    pub fn inconsistent(content: T) -> Self {
+        Group::with_mode(BreakMode::Inconsistent, content)
+    }
+
+    /// Group content so that every separator directly inside it always breaks.
+    pub fn always_broken(content: T) -> Self {
+        Group::with_mode(BreakMode::Always, content)
+    }
+
+    /// Group content so that no separator directly inside it ever breaks.
+    pub fn never_broken(content: T) -> Self {
+        Group::with_mode(BreakMode::Never, content)
+    }
+
+    /// Group content so that, if it does not fit, only the lowest priority [`PrioritySep`]s
+    /// directly inside it break, and higher priorities break in turn until it does fit.  Ordinary
+    /// separators inside the group always break when it does not fit.
+    ///
+    /// [`PrioritySep`]: struct.PrioritySep.html
+    pub fn prioritized(content: T) -> Self {
+        Group::with_mode(BreakMode::Prioritized, content)
+    }
+
+    fn with_mode(mode: BreakMode, content: T) -> Self {
+        let size = match mode {
+            BreakMode::Always => Size::MultiLine,
+            _ => content.size(),
+        };
         Group {
-            size: content.size(),
+            size,
+            mode,
+            id: None,
             content,
         }
     }
+
+    /// Record whether or not this group breaks under the given ID, so that an [`IfBroken`] rendered
+    /// after it can refer to the decision.
+    ///
+    /// [`IfBroken`]: struct.IfBroken.html
+    pub fn with_id(mut self, id: GroupId) -> Self {
+        self.id = Some(id);
+        self
+    }
 }
 
 impl<T: Pretty> Pretty for Group<T> {
@@ -302,181 +1449,2037 @@ impl<T: Pretty> Pretty for Group<T> {
         self.size
     }
 
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        self.content.break_prefix(self.mode != BreakMode::Never)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.broken = match self.mode {
+            BreakMode::Consistent | BreakMode::Inconsistent | BreakMode::Prioritized => {
+                context.overflows(self.size)
+            }
+            BreakMode::Always => true,
+            BreakMode::Never => false,
+        };
+        context.inconsistent = self.mode == BreakMode::Inconsistent;
+        context.priority = None;
+        if let Some(id) = self.id {
+            context.writer.groups.insert(id, context.broken);
+        }
+        if self.mode != BreakMode::Prioritized || !context.broken {
+            return self.content.pretty_write(context);
+        }
+
+        // The last trial render of an enclosing prioritized group rendered this group in exactly
+        // the same context, so reuse the priority it chose.  Otherwise, find the lowest priority
+        // which makes the content fit, skipping over priorities which no separator uses.  Either
+        // way, the groups inside it reuse the choices they made in the same trial.
+        let (priority, nested) = match context.writer.replayed_priorities.pop_front() {
+            Some(choice) => (choice.priority, choice.nested),
+            None => {
+                let mut priority = 0;
+                loop {
+                    context.priority = Some(priority);
+                    match context.trial(&self.content) {
+                        (false, Some(skipped), _) => priority = skipped,
+                        (_, _, chosen) => break (priority, chosen),
+                    }
+                }
+            }
+        };
+        context.priority = Some(priority);
+
+        // The choices of the groups inside this one are kept apart from those of the groups around
+        // it, so that they cannot be matched up with the wrong groups.
+        let replayed = mem::replace(&mut context.writer.replayed_priorities, nested.into());
+        let chosen = context.writer.chosen_priorities.take();
+        if chosen.is_some() {
+            context.writer.chosen_priorities = Some(Vec::new());
+        }
+        let skipped = context.writer.skipped_priority;
+        self.content.pretty_write(context.reborrow())?;
+        context.writer.skipped_priority = skipped;
+        context.writer.replayed_priorities = replayed;
+        if let Some(mut chosen) = chosen {
+            let nested = context.writer.chosen_priorities.take().unwrap_or_default();
+            chosen.push(Choice { priority, nested });
+            context.writer.chosen_priorities = Some(chosen);
+        }
+        Ok(())
+    }
+
+    fn to_doc(&self) -> Doc {
+        let content = self.content.to_doc();
+        let group = match self.mode {
+            BreakMode::Consistent => Doc::Group(Box::new(content)),
+            BreakMode::Inconsistent => Doc::Group(Box::new(content.group_lines())),
+            BreakMode::Prioritized => Doc::Group(Box::new(content.group_priorities())),
+            BreakMode::Always => Doc::Broken(Box::new(content)),
+            BreakMode::Never => Doc::Flat(Box::new(content)),
+        };
+        match self.id {
+            Some(id) => Doc::Label(id, Box::new(group)),
+            None => group,
+        }
+    }
+}
+
+/// A whitespace separator, rendered as a space if unbroken or a newline if broken.
+///
+/// The most common uses of `Sep` are `Sep(1)`, which renders as a single space or a newline, and
+/// `Sep(0)`, which introduces a point where a newline will be inserted if the content is broken.
+///
+/// # Examples
+///
+/// Breaking into multiple lines:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Sep, to_string};
+///
+/// let max_line = Some(10);
+/// let tab_size = 4;
+///
+/// // Exceeding the line length without a separator:
+/// assert_eq!(to_string(&"hello".join("world!"), max_line, tab_size), "helloworld!");
+///
+/// let expected_broken = "\
+/// hello
+/// world!";
+///
+/// assert_eq!(
+///     to_string(&"hello".join(Sep(0)).join("world!"), max_line, tab_size),
+///     expected_broken
+/// );
+///
+/// assert_eq!(
+///     to_string(&"hello".join(Sep(1)).join("world!"), max_line, tab_size),
+///     expected_broken
+/// );
+/// ```
+///
+/// Introducing spaces on a single line:
+///
+/// ```
+/// # use pretty_trait::{JoinExt, Sep, to_string};
+/// # let tab_size = 4;
+/// #
+/// assert_eq!(
+///     to_string(&"hello".join(Sep(1)).join("world!"), None, tab_size),
+///     "hello world!"
+/// );
+///
+/// assert_eq!(
+///     to_string(&"hello".join(Sep(0)).join("world!"), None, tab_size),
+///     "helloworld!"
+/// );
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Sep(pub usize);
+
+impl Pretty for Sep {
+    fn size(&self) -> Size {
+        Size::Size(self.0)
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        if broken {
+            Some(Size::Size(0))
+        } else {
+            None
+        }
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        if context.breaks(self.size()) {
+            context.newline()?;
+        } else {
+            for _ in 0..self.0 {
+                write!(context.writer, " ")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Line(self.0)
+    }
+}
+
+/// A separator with custom renderings for when it is broken and unbroken.
+///
+/// `SepWith(flat, before, after)` renders as `flat` if unbroken.  If broken, it renders as
+/// `before`, followed by a newline and the current indentation, followed by `after`.  It breaks in
+/// exactly the same circumstances as a [`Sep`] would.
+///
+/// # Examples
+///
+/// Shell line continuations:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Indent, SepWith, to_string};
+///
+/// let command = Group::new(
+///     "cargo build".join(Indent(SepWith(" ", " \\", "").join("--release"))),
+/// );
+///
+/// assert_eq!(to_string(&command, None, 4), "cargo build --release");
+/// assert_eq!(to_string(&command, Some(20), 4), "cargo build \\\n    --release");
+/// ```
+///
+/// Leading operators:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, SepWith, to_string};
+///
+/// let sum = Group::new("first".join(SepWith(" + ", "", "+ ")).join("second"));
+///
+/// assert_eq!(to_string(&sum, None, 4), "first + second");
+/// assert_eq!(to_string(&sum, Some(10), 4), "first\n+ second");
+/// ```
+///
+/// [`Sep`]: struct.Sep.html
+#[derive(Clone, Copy, Debug)]
+pub struct SepWith<F, B, A>(pub F, pub B, pub A);
+
+impl<F: Pretty, B: Pretty, A: Pretty> Pretty for SepWith<F, B, A> {
+    fn size(&self) -> Size {
+        self.0.size()
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        if broken {
+            Some(self.1.size())
+        } else {
+            self.0.break_prefix(false)
+        }
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        if context.breaks(self.size()) {
+            {
+                let mut before_context = context.reborrow();
+                before_context.trailing = Size::Size(0);
+                self.1.pretty_write(before_context)?;
+            }
+            context.newline()?;
+            self.2.pretty_write(context)
+        } else {
+            self.0.pretty_write(context)
+        }
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::FlatAlt(
+            Box::new(Doc::Concat(vec![self.1.to_doc(), Doc::Line(0), self.2.to_doc()])),
+            Box::new(self.0.to_doc()),
+        )
+    }
+}
+
+/// A separator with a priority, which only breaks in a group created with [`Group::prioritized`]
+/// once every separator of lower priority in the group has broken.
+///
+/// `PrioritySep(priority, n)` renders as `n` spaces if unbroken or a newline if broken.  Outside a
+/// prioritized group it behaves exactly like `Sep(n)`.  Low priorities are intended for the
+/// separators between loosely bound parts of the content, such as the operands of `||`, which
+/// should be broken before those between tightly bound parts, such as the operands of `&&`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, PrioritySep, to_string};
+///
+/// let sum = Group::prioritized(
+///     "a * b"
+///         .join(PrioritySep(0, 1))
+///         .join("+ c *")
+///         .join(PrioritySep(1, 1))
+///         .join("d"),
+/// );
+///
+/// assert_eq!(to_string(&sum, None, 4), "a * b + c * d");
+/// assert_eq!(to_string(&sum, Some(8), 4), "a * b\n+ c * d");
+/// ```
+///
+/// [`Group::prioritized`]: struct.Group.html#method.prioritized
+#[derive(Clone, Copy, Debug)]
+pub struct PrioritySep(pub usize, pub usize);
+
+impl Pretty for PrioritySep {
+    fn size(&self) -> Size {
+        Size::Size(self.1)
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        if broken {
+            Some(Size::Size(0))
+        } else {
+            None
+        }
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        let priority = self.0;
+        let allowed = match context.priority {
+            Some(max) if priority > max => {
+                if context.broken {
+                    let skipped = &mut context.writer.skipped_priority;
+                    *skipped = Some(skipped.map_or(priority, |min| cmp::min(min, priority)));
+                }
+                false
+            }
+            _ => true,
+        };
+        if allowed && context.breaks(self.size()) {
+            context.newline()?;
+        } else {
+            for _ in 0..self.1 {
+                write!(context.writer, " ")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::PriorityLine(self.0, self.1)
+    }
+}
+
+/// An unconditional newline.
+///
+/// A `Newline` does not by itself cause its environment to break.  Instead, it is measured as
+/// [`Size::Lines`], so a group containing it only breaks if the first, widest or last of its lines
+/// would not fit.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Newline, to_string};
+///
+/// let expected = "\
+/// hello
+/// world";
+///
+/// assert_eq!(to_string(&"hello".join(Newline).join("world"), None, 4), expected);
+/// ```
+///
+/// Keeping the opening line of a call flat around a multi-line argument:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Indent, Newline, Sep, doc, to_string};
+///
+/// let arg = "{".join(Indent(Newline.join("body"))).join(Newline).join("}");
+/// let call = Group::new("foo(".join(Sep(0)).join(arg).join(")"));
+///
+/// assert_eq!(to_string(&call, Some(20), 4), "foo({\n    body\n})");
+/// assert_eq!(to_string(&call, Some(4), 4), "foo(\n{\n    body\n})");
+/// assert_eq!(doc::to_string(&call.to_doc(), Some(20), 4), "foo({\n    body\n})");
+/// assert_eq!(doc::to_string(&call.to_doc(), Some(4), 4), "foo(\n{\n    body\n})");
+/// ```
+///
+/// The lines after a `Newline` inside an [`Align`] or [`Hang`] are measured from the column at
+/// which it starts, so they move along with any text before it on the same line:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Align, Newline, Sep, doc, to_string};
+///
+/// let binding = Group::new("let x =".join(Sep(1)).join(Align("a".join(Newline).join("bbbb"))));
+///
+/// assert_eq!(to_string(&binding, Some(20), 4), "let x = a\n        bbbb");
+/// assert_eq!(to_string(&binding, Some(11), 4), "let x =\na\nbbbb");
+/// assert_eq!(doc::to_string(&binding.to_doc(), Some(20), 4), "let x = a\n        bbbb");
+/// assert_eq!(doc::to_string(&binding.to_doc(), Some(11), 4), "let x =\na\nbbbb");
+/// ```
+///
+/// [`Size::Lines`]: enum.Size.html#variant.Lines
+/// [`Align`]: struct.Align.html
+/// [`Hang`]: struct.Hang.html
+#[derive(Clone, Copy, Debug)]
+pub struct Newline;
+
+impl Pretty for Newline {
+    fn size(&self) -> Size {
+        Size::Lines(Lines::new(0, 0, 0))
+    }
+
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        Some(Size::Size(0))
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.newline()
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::HardLine
+    }
+}
+
+/// A vertical separator, rendered as a space if unbroken or as the given number of line breaks if
+/// broken.
+///
+/// `VSep(2)` is useful for separating items such as function definitions by a blank line.  Unlike
+/// consecutive [`Newline`]s, consecutive `VSep`s are merged, so that only the largest number of
+/// line breaks among them is written.  This means that an item which is empty does not result in
+/// extra blank lines.  Blank lines never contain trailing whitespace, so they contain no
+/// indentation except for any prefix added by [`Prefix`], such as `//`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Indent, VSep, to_string};
+///
+/// let items = Group::new(
+///     "fn a() {}".join(VSep(2)).join("fn b() {}").join(VSep(2)).join("fn c() {}"),
+/// );
+/// let module = "mod m {".join(Indent(VSep(1).join(items))).join(VSep(1)).join("}");
+///
+/// let expected = "\
+/// mod m {
+///     fn a() {}
+///
+///     fn b() {}
+///
+///     fn c() {}
+/// }";
+///
+/// assert_eq!(to_string(&module, Some(20), 4), expected);
+/// assert_eq!(to_string(&items, None, 4), "fn a() {} fn b() {} fn c() {}");
+/// ```
+///
+/// Collapsing the separators around an empty item:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, VSep, doc, to_string};
+///
+/// let items = Group::new("a".join(VSep(2)).join("").join(VSep(2)).join("b"));
+///
+/// assert_eq!(to_string(&items, Some(1), 4), "a\n\nb");
+/// assert_eq!(doc::to_string(&items.to_doc(), Some(1), 4), "a\n\nb");
+/// ```
+///
+/// The line after merged separators is indented by the last of them, so a trailing `VSep` inside
+/// an [`Indent`] does not indent what follows it:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Indent, VSep, doc, to_string, to_string_optimal};
+///
+/// let module = Group::new(
+///     "mod m {".join(Indent(VSep(1).join("fn a() {}").join(VSep(2)))).join(VSep(1)).join("}"),
+/// );
+///
+/// let expected = "\
+/// mod m {
+///   fn a() {}
+///
+/// }";
+///
+/// assert_eq!(to_string(&module, Some(8), 2), expected);
+/// assert_eq!(doc::to_string(&module.to_doc(), Some(8), 2), expected);
+/// assert_eq!(to_string_optimal(&module, Some(8), 2), expected);
+/// ```
+///
+/// [`Newline`]: struct.Newline.html
+/// [`Indent`]: struct.Indent.html
+/// [`Prefix`]: struct.Prefix.html
+#[derive(Clone, Copy, Debug)]
+pub struct VSep(pub usize);
+
+impl Pretty for VSep {
+    fn size(&self) -> Size {
+        Size::Size(1)
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        if broken {
+            Some(Size::Size(0))
+        } else {
+            None
+        }
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        if context.breaks(self.size()) {
+            context.break_lines(self.0)
+        } else {
+            write!(context.writer, " ")
+        }
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::FlatAlt(
+            Box::new(Doc::HardLines(self.0)),
+            Box::new(Doc::Text(" ".to_string())),
+        )
+    }
+}
+
+/// A wrapper whose contents are deferred to the end of the current line.
+///
+/// The contents are rendered just before the next newline, or at the end of the output if there
+/// are no more newlines.  This is useful for trailing comments, which would otherwise end up in the
+/// middle of a line if the surrounding content were not broken.  Because of this, a `LineSuffix`
+/// always causes its environment to break.  The contents do not count towards the width of the text
+/// around the `LineSuffix` when deciding whether a group fits, but the `LineSuffix` is not a
+/// possible line break either, so any text following it still counts towards the group before it.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Sep, LineSuffix, to_string};
+///
+/// let call = Group::new(
+///     "foo(a,"
+///         .join(LineSuffix(" // first"))
+///         .join(Sep(1))
+///         .join("b)")
+///         .join(LineSuffix(" // last")),
+/// );
+///
+/// let expected = "\
+/// foo(a, // first
+/// b) // last";
+///
+/// assert_eq!(to_string(&call, Some(80), 4), expected);
+/// ```
+///
+/// Text following a `LineSuffix` counts towards the fit of the group before it:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Sep, LineSuffix, to_string};
+///
+/// let statement = Group::new("aaaa".join(Sep(1)).join("bbbb"))
+///     .join(LineSuffix(" // c"))
+///     .join(";;;;");
+///
+/// assert_eq!(to_string(&statement, Some(9), 4), "aaaa\nbbbb;;;; // c");
+/// ```
+///
+/// A `LineSuffix` on an otherwise empty line stays on that line:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Indent, LineSuffix, Newline, doc, to_string};
+///
+/// let block = "{"
+///     .join(Indent(Newline.join(LineSuffix("// only a comment")).join(Newline).join("x")))
+///     .join(Newline)
+///     .join("}");
+///
+/// let expected = "\
+/// {
+///   // only a comment
+///   x
+/// }";
+///
+/// assert_eq!(to_string(&block, None, 2), expected);
+/// assert_eq!(doc::to_string(&block.to_doc(), None, 2), expected);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct LineSuffix<T>(pub T);
+
+impl<T: Pretty> Pretty for LineSuffix<T> {
+    fn size(&self) -> Size {
+        Size::MultiLine
+    }
+
+    fn broken_size(&self) -> Size {
+        // The contents take up no space on the current line until it ends.
+        Size::Size(0)
+    }
+
+    fn pretty_write(&self, context: Context) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = Writer::new(&mut buffer);
+            writer.column = context.writer.column;
+            self.0.pretty_write(context.with_writer(&mut writer))?;
+            writer.finish()?;
+        }
+        context.writer.push_line_suffix(&buffer)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::LineSuffix(Box::new(self.0.to_doc()))
+    }
+}
+
+/// A wrapper which indents any newlines inside its contents.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Sep, Indent, to_string};
+///
+/// let max_line = Some(20);
+/// let tab_size = 4;
+///
+/// let expected = "\
+/// (
+///     lorem
+///     ipsum
+///     dolor
+///     sit
+///     amet
+/// )";
+///
+/// assert_eq!(
+///     to_string(
+///         &"(".join(Indent(
+///             Sep(0)
+///                 .join("lorem")
+///                 .join(Sep(1))
+///                 .join("ipsum")
+///                 .join(Sep(1))
+///                 .join("dolor")
+///                 .join(Sep(1))
+///                 .join("sit")
+///                 .join(Sep(1))
+///                 .join("amet")
+///         )).join(Sep(0)).join(")"),
+///         max_line,
+///         tab_size,
+///     ),
+///     expected
+/// );
+/// ```
+///
+/// # Caution
+///
+/// To indent a block enclosed in paired delimiters like brackets, care must be taken to ensure that
+/// the first line of the content *is* indented, and that the closing delimiter *is not* indented
+/// along with its contents.  To ensure this, the newline after the opening delimiter should occur
+/// *inside* the `Indent` block, and the newline before the closing delimiter should occur *outside*
+/// the `Indent` block, as in the example above.  The [`block`] function implements this pattern.
+///
+/// [`block`]: fn.block.html
+#[derive(Clone, Copy, Debug)]
+pub struct Indent<T>(pub T);
+
+impl<T: Pretty> Pretty for Indent<T> {
+    fn size(&self) -> Size {
+        self.0.size().indented(1, 0)
+    }
+
+    fn broken_size(&self) -> Size {
+        self.0.broken_size().indented(1, 0)
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.0.break_prefix(broken)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.indent_level += 1;
+        context.indent = context.indent.with_spaces(context.tab_size);
+        self.0.pretty_write(context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Nest(1, Box::new(self.0.to_doc()))
+    }
+}
+
+/// A wrapper which indents any newlines inside its contents by a given number of columns.
+///
+/// Unlike [`Indent`], which always indents by `tab_size` columns, `IndentBy` can be used for
+/// indentation which should not depend on the tab size, such as a continuation indent.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Newline, IndentBy, to_string};
+///
+/// let expected = "\
+/// let x =
+///         continued";
+///
+/// assert_eq!(
+///     to_string(&"let x =".join(IndentBy(8, Newline.join("continued"))), None, 4),
+///     expected
+/// );
+/// ```
+///
+/// [`Indent`]: struct.Indent.html
+#[derive(Clone, Copy, Debug)]
+pub struct IndentBy<T>(pub usize, pub T);
+
+impl<T: Pretty> Pretty for IndentBy<T> {
+    fn size(&self) -> Size {
+        self.1.size().indented(0, self.0)
+    }
+
+    fn broken_size(&self) -> Size {
+        self.1.broken_size().indented(0, self.0)
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.1.break_prefix(broken)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.indent = context.indent.with_spaces(self.0);
+        self.1.pretty_write(context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        let spaces = (0..self.0).map(|_| ' ').collect();
+        Doc::Prefix(spaces, Box::new(self.1.to_doc()))
+    }
+}
+
+/// A wrapper which inserts a prefix string at the start of every new line inside its contents.
+///
+/// The prefix is added after any existing indentation, so prefixes and indentation can be nested
+/// and stacked freely.  The prefix is not inserted before the first line of the contents, which
+/// starts wherever the preceding content left off.
+///
+/// # Examples
+///
+/// Rendering a comment:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Newline, Indent, Prefix, to_string};
+///
+/// let comment = "// ".join(Prefix("// ", "hello".join(Newline).join("world")));
+///
+/// let expected = "\
+/// fn main() {
+///     // hello
+///     // world
+/// }";
+///
+/// let function = "fn main() {".join(Indent(Newline.join(comment))).join(Newline).join("}");
+///
+/// assert_eq!(to_string(&function, None, 4), expected);
+/// ```
+///
+/// Stacking prefixes:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Newline, Prefix, to_string};
+///
+/// let quoted = "> ".join(Prefix("> ", "> ".join(Prefix("> ", "a".join(Newline).join("b")))));
+///
+/// assert_eq!(to_string(&quoted, None, 4), "> > a\n> > b");
+/// ```
+///
+/// Blank lines keep the prefix, without any trailing whitespace:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Newline, Prefix, doc, to_string};
+///
+/// let comment = "// ".join(Prefix("// ", "a".join(Newline).join(Newline).join("b")));
+///
+/// assert_eq!(to_string(&comment, None, 4), "// a\n//\n// b");
+/// assert_eq!(doc::to_string(&comment.to_doc(), None, 4), "// a\n//\n// b");
+///
+/// // A blank line which starts outside the prefix does not get it:
+///
+/// let after = "a".join(Newline).join(Prefix("// ", Newline.join("x")));
+///
+/// assert_eq!(to_string(&after, None, 4), "a\n\n// x");
+/// assert_eq!(doc::to_string(&after.to_doc(), None, 4), "a\n\n// x");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Prefix<S, T>(pub S, pub T);
+
+impl<S: AsRef<str>, T: Pretty> Pretty for Prefix<S, T> {
+    fn size(&self) -> Size {
+        self.1.size().indented(0, self.0.as_ref().chars().count())
+    }
+
+    fn broken_size(&self) -> Size {
+        self.1.broken_size().indented(0, self.0.as_ref().chars().count())
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.1.break_prefix(broken)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.indent = context.indent.with_prefix(self.0.as_ref());
+        self.1.pretty_write(context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Prefix(self.0.as_ref().to_string(), Box::new(self.1.to_doc()))
+    }
+}
+
+/// A wrapper which resets the indentation of any newlines inside its contents to a fixed number of
+/// columns, regardless of any enclosing indentation.
+///
+/// `Dedent(0, x)` removes indentation entirely, which is useful for content such as heredocs and
+/// multi-line string literals.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Newline, Indent, Dedent, to_string};
+///
+/// let heredoc = "cat <<EOF".join(Dedent(0, Newline.join("raw text").join(Newline).join("EOF")));
+///
+/// let expected = "\
+/// run {
+///     cat <<EOF
+/// raw text
+/// EOF
+/// }";
+///
+/// assert_eq!(
+///     to_string(&"run {".join(Indent(Newline.join(heredoc))).join(Newline).join("}"), None, 4),
+///     expected
+/// );
+/// ```
+///
+/// The lines after the first are measured from the column they are dedented to, so an enclosing
+/// group still fits if each of them does:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Newline, Sep, Dedent, doc, to_string};
+///
+/// let group = Group::new("x".join(Sep(1)).join(Dedent(0, "a".join(Newline).join("b"))));
+///
+/// assert_eq!(to_string(&group, Some(10), 4), "x a\nb");
+/// assert_eq!(doc::to_string(&group.to_doc(), Some(10), 4), "x a\nb");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Dedent<T>(pub usize, pub T);
+
+impl<T: Pretty> Pretty for Dedent<T> {
+    fn size(&self) -> Size {
+        self.1.size().dedented(self.0)
+    }
+
+    fn broken_size(&self) -> Size {
+        self.1.broken_size().dedented(self.0)
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.1.break_prefix(broken)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.indent = Indentation::spaces(self.0);
+        self.1.pretty_write(context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Dedent(self.0, Box::new(self.1.to_doc()))
+    }
+}
+
+/// A wrapper which imposes a narrower maximum line length on its contents.
+///
+/// Inside `MaxLine(n, x)`, lines are laid out as if the maximum line length were `n`, or the
+/// enclosing maximum line length if that is narrower.  Like the maximum line length passed to
+/// [`write`], `n` is measured from the start of the line, including any indentation.  The contents
+/// are still measured normally when the environment around them decides whether or not to break.
+///
+/// # Examples
+///
+/// Wrapping a comment more narrowly than the surrounding code:
+///
+/// ```
+/// use pretty_trait::{JoinExt, MaxLine, Prefix, Words, to_string, to_string_optimal};
+///
+/// let text = "Returns the number of widgets in the frobnicator.";
+/// let comment = "// ".join(Prefix("// ", MaxLine(24, Words(text))));
+///
+/// let expected = "\
+/// // Returns the number of
+/// // widgets in the
+/// // frobnicator.";
+///
+/// assert_eq!(to_string(&comment, Some(100), 4), expected);
+/// assert_eq!(to_string_optimal(&comment, Some(100), 4), expected);
+/// ```
+///
+/// [`write`]: fn.write.html
+#[derive(Clone, Copy, Debug)]
+pub struct MaxLine<T>(pub usize, pub T);
+
+impl<T: Pretty> Pretty for MaxLine<T> {
+    fn size(&self) -> Size {
+        self.1.size()
+    }
+
+    fn broken_size(&self) -> Size {
+        self.1.broken_size()
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.1.break_prefix(broken)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.max_line = Some(context.max_line.map_or(self.0, |max| cmp::min(max, self.0)));
+        self.1.pretty_write(context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::MaxLine(self.0, Box::new(self.1.to_doc()))
+    }
+}
+
+/// A wrapper which indents any newlines inside its contents to the column at which it starts.
+///
+/// # Examples
+///
+/// Aligning function arguments under the opening parenthesis:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Sep, Align, delimited, to_string};
+///
+/// let max_line = Some(16);
+/// let tab_size = 4;
+///
+/// let args = delimited(&",".join(Sep(1)), &["alpha", "beta", "gamma"]);
+///
+/// let expected = "\
+/// foo(alpha,
+///     beta,
+///     gamma)";
+///
+/// assert_eq!(
+///     to_string(&"foo(".join(Align(args)).join(")"), max_line, tab_size),
+///     expected
+/// );
+/// ```
+///
+/// Keeping a call flat when an aligned argument is followed by another multi-line argument:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Group, Newline, Sep, Align, doc, to_string};
+///
+/// let list = Align("[a,".join(Newline).join(" b]"));
+/// let call = Group::new(
+///     "f(".join(Sep(0)).join(list).join(",").join(Sep(1)).join("c".join(Newline).join("d")).join(")"),
+/// );
+///
+/// let expected = "\
+/// f([a,
+///    b], c
+/// d)";
+///
+/// assert_eq!(to_string(&call, Some(20), 4), expected);
+/// assert_eq!(doc::to_string(&call.to_doc(), Some(20), 4), expected);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Align<T>(pub T);
+
+impl<T: Pretty> Pretty for Align<T> {
+    fn size(&self) -> Size {
+        self.0.size().aligned(0)
+    }
+
+    fn broken_size(&self) -> Size {
+        self.0.broken_size().aligned(0)
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.0.break_prefix(broken)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.indent = context.indent.aligned_to(context.writer.column());
+        self.0.pretty_write(context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Align(0, Box::new(self.0.to_doc()))
+    }
+}
+
+/// A wrapper which indents any newlines inside its contents to the given number of columns past
+/// the column at which it starts.
+///
+/// `Hang(0, x)` is equivalent to `Align(x)`.
+///
+/// # Examples
+///
+/// Indenting the body of a Lisp form relative to its opening parenthesis:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Sep, Hang, to_string};
+///
+/// let max_line = Some(16);
+/// let tab_size = 4;
+///
+/// let form = "(".join(Hang(1, "let ((x 1))".join(Sep(1)).join("(print x)"))).join(")");
+///
+/// let expected = "\
+/// (let ((x 1))
+///   (print x))";
+///
+/// assert_eq!(to_string(&form, max_line, tab_size), expected);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Hang<T>(pub usize, pub T);
+
+impl<T: Pretty> Pretty for Hang<T> {
+    fn size(&self) -> Size {
+        self.1.size().aligned(self.0)
+    }
+
+    fn broken_size(&self) -> Size {
+        self.1.broken_size().aligned(self.0)
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.1.break_prefix(broken)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        context.indent = context.indent.aligned_to(context.writer.column() + self.0);
+        self.1.pretty_write(context)
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Align(self.0, Box::new(self.1.to_doc()))
+    }
+}
+
+/// A wrapper which pads its contents to a fixed width, aligning them to the left, right or centre
+/// of the field.
+///
+/// The padding is made up of a fill character, which is a space unless changed with
+/// [`with_fill`].  The width of the contents is taken from their [`size`], so contents which are
+/// already at least as wide as the field are rendered without padding, and contents which span
+/// multiple lines are rendered without padding at all.
+///
+/// The padded contents are kept on one line if they fit.  Otherwise, if the contents can break,
+/// they are rendered without any padding, as if in a [`Group`] which does not fit.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Pad, to_string};
+///
+/// assert_eq!(to_string(&Pad::left(6, "ab").join("|"), None, 4), "ab    |");
+/// assert_eq!(to_string(&Pad::right(6, "42"), None, 4), "    42");
+/// assert_eq!(to_string(&Pad::center(9, " Title ").with_fill('='), None, 4), "= Title =");
+///
+/// // Contents wider than the field are not truncated:
+///
+/// assert_eq!(to_string(&Pad::right(2, "12345"), None, 4), "12345");
+/// ```
+///
+/// Padded contents which do not fit:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Pad, Sep, to_string};
+///
+/// let field = Pad::right(12, Group::new("aaa".join(Sep(1)).join("bbb"))).join("|");
+///
+/// assert_eq!(to_string(&field, Some(14), 4), "     aaa bbb|");
+/// assert_eq!(to_string(&field, Some(10), 4), "aaa bbb|");
+/// assert_eq!(to_string(&field, Some(6), 4), "aaa\nbbb|");
+/// ```
+///
+/// [`with_fill`]: #method.with_fill
+/// [`size`]: trait.Pretty.html#tymethod.size
+/// [`Group`]: struct.Group.html
+#[derive(Clone, Copy, Debug)]
+pub struct Pad<T> {
+    width: usize,
+    alignment: Alignment,
+    fill: char,
+    content: T,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+impl<T: Pretty> Pad<T> {
+    /// Pad content on the right, so that it is aligned to the left of the field.
+    pub fn left(width: usize, content: T) -> Self {
+        Pad::with_alignment(width, Alignment::Left, content)
+    }
+
+    /// Pad content on the left, so that it is aligned to the right of the field.
+    pub fn right(width: usize, content: T) -> Self {
+        Pad::with_alignment(width, Alignment::Right, content)
+    }
+
+    /// Pad content on both sides, so that it is centred in the field.  If the padding cannot be
+    /// split evenly, the extra character goes on the right.
+    pub fn center(width: usize, content: T) -> Self {
+        Pad::with_alignment(width, Alignment::Center, content)
+    }
+
+    fn with_alignment(width: usize, alignment: Alignment, content: T) -> Self {
+        Pad {
+            width,
+            alignment,
+            fill: ' ',
+            content,
+        }
+    }
+
+    /// Pad with the given character instead of spaces.
+    pub fn with_fill(mut self, fill: char) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// The number of fill characters before and after the contents, or `None` if the contents span
+    /// multiple lines.
+    fn padding(&self) -> Option<(usize, usize)> {
+        let padding = match self.content.size() {
+            Size::Size(width) => self.width.saturating_sub(width),
+            Size::Lines(_) | Size::MultiLine => return None,
+        };
+        Some(match self.alignment {
+            Alignment::Left => (0, padding),
+            Alignment::Right => (padding, 0),
+            Alignment::Center => (padding / 2, padding - padding / 2),
+        })
+    }
+
+    fn fill_text(&self, count: usize) -> String {
+        (0..count).map(|_| self.fill).collect()
+    }
+}
+
+impl<T: Pretty> Pretty for Pad<T> {
+    fn size(&self) -> Size {
+        match self.padding() {
+            Some((before, after)) => Size::Size(before) + self.content.size() + Size::Size(after),
+            None => Size::MultiLine,
+        }
+    }
+
+    fn broken_size(&self) -> Size {
+        match self.padding() {
+            Some((before, after)) => {
+                Size::Size(before) + self.content.broken_size() + Size::Size(after)
+            }
+            None => Size::MultiLine,
+        }
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        let before = self.padding().map_or(0, |(before, _)| before);
+        self.content
+            .break_prefix(broken)
+            .map(|prefix| Size::Size(before) + prefix)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        let (before, after) = match self.padding() {
+            Some(padding) if !context.overflows(self.size()) => {
+                context.broken = false;
+                padding
+            }
+            Some(_) if self.content.break_prefix(true).is_some() => {
+                context.broken = true;
+                (0, 0)
+            }
+            Some(padding) => padding,
+            None => (0, 0),
+        };
+        write!(context.writer, "{}", self.fill_text(before))?;
+        {
+            let mut content_context = context.reborrow();
+            content_context.trailing = Size::Size(after) + content_context.trailing;
+            self.content.pretty_write(content_context)?;
+        }
+        write!(context.writer, "{}", self.fill_text(after))
+    }
+
+    fn to_doc(&self) -> Doc {
+        let content = self.content.to_doc();
+        let (before, after) = match self.padding() {
+            Some(padding) => padding,
+            None => return content,
+        };
+        let padded = Doc::Concat(vec![
+            Doc::Text(self.fill_text(before)),
+            Doc::Flat(Box::new(content.clone())),
+            Doc::Text(self.fill_text(after)),
+        ]);
+        if self.content.break_prefix(true).is_some() {
+            Doc::Union(Box::new(padded), Box::new(Doc::Broken(Box::new(content))))
+        } else {
+            padded
+        }
+    }
+}
+
+/// A wrapper which chooses between two alternative layouts for the same content.
+///
+/// The first layout is used if every line of it, including any text which immediately follows the
+/// `Alt`, would fit within the maximum line length, as measured by its [`size`], with any groups
+/// inside it unbroken.  Otherwise, the second layout is used.  To choose between more than two
+/// layouts, nest `Alt`s in the second position, as in `Alt(a, Alt(b, c))`; the last layout is used
+/// if none of the others fit.
+///
+/// When measured, an `Alt` reports the size of its narrowest layout.
+///
+/// # Examples
+///
+/// Wrapping a closure body in braces only when it does not fit:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Alt, Indent, Newline, to_string};
+///
+/// let max_line = Some(24);
+/// let tab_size = 4;
+///
+/// let closure = |body| "x => ".join(Alt(
+///     body,
+///     "{".join(Indent(Newline.join(body))).join(Newline).join("}"),
+/// ));
+///
+/// assert_eq!(to_string(&closure("compute(alpha)"), max_line, tab_size), "x => compute(alpha)");
+///
+/// let expected = "\
+/// x => {
+///     compute(alpha, beta)
+/// }";
+///
+/// assert_eq!(to_string(&closure("compute(alpha, beta)"), max_line, tab_size), expected);
+/// ```
+///
+/// Since an `Alt` is measured by its narrowest layout, an enclosing group stays flat if either
+/// layout fits, even when the narrower one spans several lines:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Alt, Group, Newline, Sep, to_string};
+///
+/// let arg = Alt("alpha + beta + gamma", "alpha".join(Newline).join("+ beta + gamma"));
+/// let call = Group::new("f(".join(Sep(0)).join(arg).join(")"));
+///
+/// assert_eq!(to_string(&call, Some(16), 4), "f(alpha\n+ beta + gamma)");
+/// ```
+///
+/// The first layout is never chosen just because its first line fits:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Alt, Group, Sep, doc, to_string};
+///
+/// let choice = Group::new(Alt("(aaaa".join(Sep(1)).join("bbbb)"), Alt("(aa bb)", "X")));
+///
+/// assert_eq!(to_string(&choice, Some(8), 4), "(aa bb)");
+/// assert_eq!(doc::to_string(&choice.to_doc(), Some(8), 4), "(aa bb)");
+/// assert_eq!(to_string(&choice, Some(6), 4), "X");
+/// assert_eq!(doc::to_string(&choice.to_doc(), Some(6), 4), "X");
+/// ```
+///
+/// [`size`]: trait.Pretty.html#tymethod.size
+#[derive(Clone, Copy, Debug)]
+pub struct Alt<T, U>(pub T, pub U);
+
+impl<T: Pretty, U: Pretty> Pretty for Alt<T, U> {
+    fn size(&self) -> Size {
+        self.0.size().narrowest(self.1.size())
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        match (self.0.break_prefix(broken), self.1.break_prefix(broken)) {
+            (None, None) => None,
+            (first, second) => {
+                let first = first.unwrap_or_else(|| size_in(&self.0, broken));
+                Some(first.narrowest(second.unwrap_or_else(|| size_in(&self.1, broken))))
+            }
+        }
+    }
+
+    fn pretty_write(&self, context: Context) -> io::Result<()> {
+        // As with a `Group`, the layout is chosen from sizes alone, so nested `Alt`s are never
+        // rendered more than once.
+        if !context.overflows(size_in(&self.0, context.broken)) {
+            self.0.pretty_write(context)
+        } else {
+            self.1.pretty_write(context)
+        }
+    }
+
+    fn to_doc(&self) -> Doc {
+        // A union only checks the first line of its first branch, so the first layout is checked
+        // with its line breaks flat, as it is measured by its size.
+        let first = Doc::Flat(Box::new(self.0.to_doc()));
+        Doc::Union(Box::new(first), Box::new(self.1.to_doc()))
+    }
+}
+
+/// A wrapper which places two blocks of lines side by side, separated by a gap of the given number
+/// of columns.
+///
+/// `Beside(gap, left, right)` renders `left` and `right` separately, each as a block of lines, and
+/// then writes them next to each other, so that each line of `right` follows the corresponding line
+/// of `left`.  The lines of `left` are padded to the width of its widest line, unless nothing
+/// follows them, and every line of the result after the first starts at the column where the
+/// `Beside` started.  `left` is laid out within the space remaining on the line, and `right` within
+/// the space remaining after `left` and the gap.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Beside, Indent, Newline, Pretty, doc, to_string};
+///
+/// let before = "fn foo() {".join(Indent(Newline.join("bar();"))).join(Newline).join("}");
+/// let after = "fn foo() {".join(Newline).join("}");
+/// let pair = Beside(3, before, after);
+///
+/// let expected = "\
+/// fn foo() {   fn foo() {
+///     bar();   }
+/// }";
+///
+/// assert_eq!(to_string(&pair, None, 4), expected);
+/// assert_eq!(doc::to_string(&pair.to_doc(), None, 4), expected);
+/// ```
+///
+/// Blank lines of the right block leave no trailing whitespace:
+///
+/// ```
+/// use pretty_trait::{Beside, to_string};
+///
+/// assert_eq!(to_string(&Beside(2, "aa\nb", "x\n\ny"), None, 4), "aa  x\nb\n    y");
+/// ```
+///
+/// The right block is laid out in the space remaining after the left block:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Beside, Group, Sep, to_string};
+///
+/// let pair = "x = ".join(Beside(1, "aaaa", Group::new("b".join(Sep(1)).join("c"))));
+///
+/// assert_eq!(to_string(&pair, Some(12), 4), "x = aaaa b c");
+/// assert_eq!(to_string(&pair, Some(10), 4), "x = aaaa b\n         c");
+/// ```
+///
+/// # Note
+///
+/// A `Beside` cannot share lines with the text surrounding it, so if either block spans multiple
+/// lines, any text following the `Beside` is written after the end of its last line.
+#[derive(Clone, Copy, Debug)]
+pub struct Beside<T, U>(pub usize, pub T, pub U);
+
+impl<T: Pretty, U: Pretty> Pretty for Beside<T, U> {
+    fn size(&self) -> Size {
+        let (left, right) = (self.1.size(), self.2.size());
+        if left.is_multi_line() || right.is_multi_line() {
+            // The lines of the blocks are interleaved, which `Size` cannot describe.
+            Size::MultiLine
+        } else {
+            left + Size::Size(self.0) + right
+        }
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        let start = context.writer.column;
+        let left_max = context.max_line.map(|max| max.saturating_sub(start));
+        let left = context.render_block(&self.1, left_max)?;
+        let right_max = left_max.map(|max| max.saturating_sub(block_width(&left) + self.0));
+        let right = context.render_block(&self.2, right_max)?;
+
+        context.indent = context.indent.aligned_to(start);
+        for (row, line) in side_by_side(self.0, &left, &right).iter().enumerate() {
+            if row > 0 {
+                context.newline()?;
+            }
+            write!(context.writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Beside(self.0, Box::new(self.1.to_doc()), Box::new(self.2.to_doc()))
+    }
+}
+
+/// The width of the widest line in a block of lines.
+fn block_width(lines: &[String]) -> usize {
+    lines.iter().map(|line| line.chars().count()).max().unwrap_or(0)
+}
+
+/// Merge two blocks of lines, so that each line of `right` follows the corresponding line of
+/// `left`, padded to the width of the widest line of `left` and then separated by `gap` columns.
+fn side_by_side(gap: usize, left: &[String], right: &[String]) -> Vec<String> {
+    let left_width = block_width(left);
+    (0..cmp::max(left.len(), right.len()))
+        .map(|row| {
+            let mut line = left.get(row).cloned().unwrap_or_default();
+            // A blank line of `right` needs no padding before it.
+            if let Some(right_line) = right.get(row).filter(|line| !line.is_empty()) {
+                let padding = left_width - line.chars().count() + gap;
+                line.extend((0..padding).map(|_| ' '));
+                line.push_str(right_line);
+            }
+            line
+        })
+        .collect()
+}
+
+/// A table of pretty-printable values, with the cells in each column aligned.
+///
+/// Each row of a `Table` is rendered on its own line, with its cells separated by a single space.
+/// Every cell except the last in each row is padded to the width of the widest cell in its column,
+/// so that the following cells line up.  A row with a cell (other than its last cell) which spans
+/// multiple lines, or which would not fit within the maximum line length when aligned, is instead
+/// rendered without padding, and does not affect the width of the columns.  Each cell is rendered
+/// as if it were wrapped in its own [`Group`].
+///
+/// # Examples
+///
+/// Aligning the types of struct fields:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Table, Indent, Newline, to_string};
+///
+/// let fields = Table(vec![
+///     vec!["x:", "i32,"],
+///     vec!["long_name:", "String,"],
+///     vec!["y:", "i32,"],
+/// ]);
+///
+/// let expected = "\
+/// struct Foo {
+///     x:         i32,
+///     long_name: String,
+///     y:         i32,
+/// }";
+///
+/// let body = "struct Foo {".join(Indent(Newline.join(fields))).join(Newline).join("}");
+///
+/// assert_eq!(to_string(&body, None, 4), expected);
+/// ```
+///
+/// Rows which would not fit when aligned are left unaligned:
+///
+/// ```
+/// use pretty_trait::{Table, to_string, to_string_optimal};
+///
+/// let arms = Table(vec![
+///     vec!["Some(x)", "=>", "x,"],
+///     vec!["None", "=>", "default_value_for_this_type(),"],
+///     vec!["_", "=>", "y,"],
+/// ]);
+///
+/// let expected = "\
+/// Some(x) => x,
+/// None => default_value_for_this_type(),
+/// _       => y,";
+///
+/// assert_eq!(to_string(&arms, Some(40), 4), expected);
+/// assert_eq!(to_string_optimal(&arms, Some(40), 4), expected);
+/// ```
+///
+/// # Note
+///
+/// When lowered to a [`Doc`], the column widths are calculated from every row which does not span
+/// multiple lines, including any row which turns out to be too wide to be aligned itself.
+///
+/// [`Group`]: struct.Group.html
+/// [`Doc`]: doc/enum.Doc.html
+#[derive(Clone, Debug)]
+pub struct Table<T>(pub Vec<Vec<T>>);
+
+impl<T: Pretty> Table<T> {
+    fn row_size(row: &[T]) -> Size {
+        row.iter().enumerate().fold(Size::Size(0), |total, (i, cell)| {
+            total + Size::Size(if i > 0 { 1 } else { 0 }) + cell.size()
+        })
+    }
+
+    /// Whether a row can be aligned at all, because none of its cells except the last spans
+    /// multiple lines.
+    fn alignable(row: &[T]) -> bool {
+        row.iter().rev().skip(1).all(|cell| !cell.size().is_multi_line())
+    }
+
+    /// Calculate the width of each column from the rows which are aligned.
+    fn widths(&self, aligned: &[bool]) -> Vec<usize> {
+        let mut widths = Vec::new();
+        for (row, _) in self.0.iter().zip(aligned).filter(|&(_, &aligned)| aligned) {
+            for (i, cell) in row.iter().enumerate().take(row.len().saturating_sub(1)) {
+                if let Size::Size(width) = cell.size() {
+                    if i >= widths.len() {
+                        widths.push(0);
+                    }
+                    widths[i] = cmp::max(widths[i], width);
+                }
+            }
+        }
+        widths
+    }
+
+    /// Lower a row to a `Doc`, padding its cells to the given column widths if it is aligned.
+    fn row_doc(row: &[T], widths: Option<&[usize]>) -> Doc {
+        let mut docs = Vec::new();
+        for (j, cell) in row.iter().enumerate() {
+            if j > 0 {
+                docs.push(Doc::Text(" ".to_string()));
+            }
+            docs.push(Doc::Group(Box::new(cell.to_doc())));
+            if let (Some(widths), Size::Size(width)) = (widths, cell.size()) {
+                if j + 1 < row.len() {
+                    let padding = widths[j].saturating_sub(width);
+                    docs.push(Doc::Text((0..padding).map(|_| ' ').collect()));
+                }
+            }
+        }
+        Doc::Concat(docs)
+    }
+
+    /// Calculate the width of each column, and which rows are aligned to them, for a table starting
+    /// at the current column.
+    fn layout(&self, context: &Context) -> (Vec<usize>, Vec<bool>) {
+        // The column at which each row starts, and the amount of the ribbon already used.
+        let column = context.writer.column;
+        let first = (column, column.saturating_sub(context.writer.line_start));
+        let rest = (context.indent.width(), 0);
+        let mut aligned: Vec<bool> = self.0.iter().map(|row| Table::alignable(row)).collect();
+        loop {
+            let widths = self.widths(&aligned);
+            let mut changed = false;
+            for (i, row) in self.0.iter().enumerate() {
+                if !aligned[i] {
+                    continue;
+                }
+                let (start, ribbon_used) = if i == 0 { first } else { rest };
+                let padded: usize = widths
+                    .iter()
+                    .take(row.len().saturating_sub(1))
+                    .map(|width| width + 1)
+                    .sum();
+                let last = row.last().map_or(Size::Size(0), Pretty::size);
+                let trailing = if i + 1 == self.0.len() {
+                    context.trailing
+                } else {
+                    Size::Size(0)
+                };
+                let width = Size::Size(padded) + last + trailing;
+                if (Size::Size(start) + width).exceeds(context.max_line) ||
+                    (Size::Size(ribbon_used) + width).exceeds(context.ribbon)
+                {
+                    aligned[i] = false;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return (widths, aligned);
+            }
+        }
+    }
+}
+
+impl<T: Pretty> Pretty for Table<T> {
+    fn size(&self) -> Size {
+        match self.0.len() {
+            0 => Size::Size(0),
+            1 => Table::row_size(&self.0[0]),
+            _ => Size::MultiLine,
+        }
+    }
+
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        if self.0.len() > 1 {
+            Some(Table::row_size(&self.0[0]))
+        } else {
+            None
+        }
+    }
+
     fn pretty_write(&self, mut context: Context) -> io::Result<()> {
-        let indented_size = self.size + Size::Size(context.indent_level * context.tab_size);
-        context.broken = indented_size.exceeds(context.max_line);
-        self.content.pretty_write(context)
+        let (widths, aligned) = self.layout(&context);
+        for (i, row) in self.0.iter().enumerate() {
+            if i > 0 {
+                context.newline()?;
+            }
+            let mut trailing = if i + 1 == self.0.len() {
+                context.trailing
+            } else {
+                Size::Size(0)
+            };
+            let mut trailings = Vec::with_capacity(row.len());
+            for cell in row.iter().rev() {
+                trailings.push(trailing);
+                trailing = Size::Size(1) + leading_size(cell, trailing, true);
+            }
+            for (j, (cell, trailing)) in row.iter().zip(trailings.into_iter().rev()).enumerate() {
+                if j > 0 {
+                    write!(context.writer, " ")?;
+                }
+                let start = context.writer.column;
+                let mut cell_context = context.reborrow();
+                cell_context.trailing = trailing;
+                cell_context.broken = cell_context.overflows(cell.size());
+                cell.pretty_write(cell_context)?;
+                if aligned[i] && j + 1 < row.len() {
+                    let width = context.writer.column.saturating_sub(start);
+                    for _ in width..widths[j] {
+                        write!(context.writer, " ")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn to_doc(&self) -> Doc {
+        let aligned: Vec<bool> = self.0.iter().map(|row| Table::alignable(row)).collect();
+        let widths = self.widths(&aligned);
+        let mut docs = Vec::new();
+        for (row, &alignable) in self.0.iter().zip(&aligned) {
+            if !docs.is_empty() {
+                docs.push(Doc::HardLine);
+            }
+            let unaligned = Table::row_doc(row, None);
+            if alignable {
+                let aligned = Doc::Flat(Box::new(Table::row_doc(row, Some(&widths))));
+                docs.push(Doc::Union(Box::new(aligned), Box::new(unaligned)));
+            } else {
+                docs.push(unaligned);
+            }
+        }
+        Doc::Concat(docs)
     }
 }
 
-/// A whitespace separator, rendered as a space if unbroken or a newline if broken.
+/// A grid of short pretty-printable values, laid out in as many equal-width columns as will fit,
+/// like the output of `ls`.
 ///
-/// The most common uses of `Sep` are `Sep(1)`, which renders as a single space or a newline, and
-/// `Sep(0)`, which introduces a point where a newline will be inserted if the content is broken.
+/// Each column is as wide as the widest item, and columns are separated by two spaces.  The number
+/// of columns is the largest that lets every row fit within the maximum line length, measuring the
+/// last item of each row by its own width, and the last row along with any text which follows the
+/// grid.  The first row starts at the current column, and every row after it starts on a new line
+/// at the current indentation.  If any item spans multiple lines, every item is placed on its own
+/// line.
 ///
 /// # Examples
 ///
-/// Breaking into multiple lines:
+/// Listing items in column-major order:
 ///
 /// ```
-/// use pretty_trait::{JoinExt, Sep, to_string};
+/// use pretty_trait::{Columns, to_string};
 ///
-/// let max_line = Some(10);
-/// let tab_size = 4;
+/// let names = vec!["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta"];
 ///
-/// // Exceeding the line length without a separator:
-/// assert_eq!(to_string(&"hello".join("world!"), max_line, tab_size), "helloworld!");
+/// let expected = "\
+/// alpha    delta    eta
+/// beta     epsilon
+/// gamma    zeta";
 ///
-/// let expected_broken = "\
-/// hello
-/// world!";
+/// assert_eq!(to_string(&Columns::new(names.clone()), Some(29), 4), expected);
 ///
-/// assert_eq!(
-///     to_string(&"hello".join(Sep(0)).join("world!"), max_line, tab_size),
-///     expected_broken
-/// );
+/// // In row-major order:
 ///
-/// assert_eq!(
-///     to_string(&"hello".join(Sep(1)).join("world!"), max_line, tab_size),
-///     expected_broken
-/// );
+/// let expected = "\
+/// alpha    beta     gamma
+/// delta    epsilon  zeta
+/// eta";
+///
+/// assert_eq!(to_string(&Columns::row_major(names), Some(29), 4), expected);
 /// ```
 ///
-/// Introducing spaces on a single line:
+/// The last column only needs to be as wide as its own items:
 ///
 /// ```
-/// # use pretty_trait::{JoinExt, Sep, to_string};
-/// # let tab_size = 4;
-/// #
-/// assert_eq!(
-///     to_string(&"hello".join(Sep(1)).join("world!"), None, tab_size),
-///     "hello world!"
-/// );
+/// use pretty_trait::{Columns, Pretty, doc, to_string};
 ///
-/// assert_eq!(
-///     to_string(&"hello".join(Sep(0)).join("world!"), None, tab_size),
-///     "helloworld!"
-/// );
+/// let columns = Columns::new(vec!["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta"]);
+///
+/// let expected = "\
+/// alpha    delta    eta
+/// beta     epsilon
+/// gamma    zeta";
+///
+/// assert_eq!(to_string(&columns, Some(21), 4), expected);
+/// assert_eq!(doc::to_string(&columns.to_doc(), Some(21), 4), expected);
 /// ```
-#[derive(Clone, Copy, Debug)]
-pub struct Sep(pub usize);
+#[derive(Clone, Debug)]
+pub struct Columns<T> {
+    items: Vec<T>,
+    row_major: bool,
+}
 
-impl Pretty for Sep {
+const COLUMN_GAP: usize = 2;
+
+impl<T: Pretty> Columns<T> {
+    /// Lay out items in columns, filling each column from top to bottom before moving on to the
+    /// next.
+    pub fn new(items: Vec<T>) -> Self {
+        Columns {
+            items,
+            row_major: false,
+        }
+    }
+
+    /// Lay out items in columns, filling each row from left to right before moving on to the next.
+    pub fn row_major(items: Vec<T>) -> Self {
+        Columns {
+            items,
+            row_major: true,
+        }
+    }
+
+    /// The width of the widest item, or `None` if some item spans multiple lines.
+    fn column_width(&self) -> Option<usize> {
+        self.items.iter().try_fold(0, |widest, item| match item.size() {
+            Size::Size(width) => Some(cmp::max(widest, width)),
+            Size::Lines(_) | Size::MultiLine => None,
+        })
+    }
+
+    /// The number of rows and columns needed to lay out the items in at most `columns` columns.
+    fn grid(&self, columns: usize) -> (usize, usize) {
+        let count = self.items.len();
+        if count == 0 {
+            return (0, 0);
+        }
+        // Both divisions round up.
+        let rows = (count - 1) / columns + 1;
+        // Avoid leaving empty columns at the end in column-major order.
+        let columns = (count - 1) / rows + 1;
+        (rows, columns)
+    }
+
+    /// Every distinct grid the items can be laid out in, as numbers of rows and columns, starting
+    /// with the fewest rows.
+    fn layouts(&self) -> Vec<(usize, usize)> {
+        let mut layouts: Vec<(usize, usize)> = Vec::new();
+        for columns in (1..self.items.len() + 1).rev() {
+            let (rows, columns) = self.grid(columns);
+            if layouts.last().map(|&(last_rows, _)| last_rows) != Some(rows) {
+                layouts.push((rows, columns));
+            }
+        }
+        layouts
+    }
+
+    /// The size of a grid with the given number of rows and columns, where every column but the
+    /// last in each row is `width` wide.
+    fn grid_size(&self, rows: usize, columns: usize, width: usize) -> Size {
+        (0..rows)
+            .map(|row| {
+                let indices = self.row_indices(row, rows, columns);
+                let last = indices.last().map_or(Size::Size(0), |&index| self.items[index].size());
+                Size::Size(indices.len().saturating_sub(1) * (width + COLUMN_GAP)) + last
+            })
+            .fold(None, |total, row| match total {
+                Some(total) => Some(total + Newline.size() + row),
+                None => Some(row),
+            })
+            .unwrap_or(Size::Size(0))
+    }
+
+    /// The indices of the items in the given row of a grid.
+    fn row_indices(&self, row: usize, rows: usize, columns: usize) -> Vec<usize> {
+        (0..columns)
+            .map(|column| if self.row_major {
+                row * columns + column
+            } else {
+                column * rows + row
+            })
+            .filter(|&index| index < self.items.len())
+            .collect()
+    }
+
+    /// Lower a grid with the given number of rows and columns to a `Doc`.  The grid is flat as a
+    /// whole, so that every row of it is measured when deciding whether it fits.
+    fn grid_doc(&self, items: &[Doc], rows: usize, columns: usize, width: usize) -> Doc {
+        let mut docs = Vec::new();
+        for row in 0..rows {
+            if row > 0 {
+                docs.push(Doc::HardLine);
+            }
+            let indices = self.row_indices(row, rows, columns);
+            let mut cells = Vec::new();
+            for (i, &index) in indices.iter().enumerate() {
+                cells.push(items[index].clone());
+                if let (true, Size::Size(item_width)) =
+                    (i + 1 < indices.len(), self.items[index].size())
+                {
+                    let padding = (width + COLUMN_GAP).saturating_sub(item_width);
+                    cells.push(Doc::Text((0..padding).map(|_| ' ').collect()));
+                }
+            }
+            docs.push(Doc::Concat(cells));
+        }
+        Doc::Flat(Box::new(Doc::Concat(docs)))
+    }
+}
+
+impl<T: Pretty> Pretty for Columns<T> {
     fn size(&self) -> Size {
-        Size::Size(self.0)
+        match (self.column_width(), self.items.last()) {
+            (Some(width), Some(last)) => {
+                Size::Size((self.items.len() - 1) * (width + COLUMN_GAP)) + last.size()
+            }
+            (Some(_), None) => Size::Size(0),
+            (None, _) => Size::MultiLine,
+        }
     }
 
-    fn pretty_write(&self, context: Context) -> io::Result<()> {
-        if context.broken {
-            writeln!(context.writer, "")?;
-            for _ in 0..(context.tab_size * context.indent_level) {
-                write!(context.writer, " ")?;
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        self.items.first().map(Pretty::size)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        let count = self.items.len();
+        // Use the grid with the fewest rows which fits, or a single column if none does.
+        let (width, (rows, columns)) = match self.column_width() {
+            Some(width) => {
+                let layout = self.layouts().into_iter().find(|&(rows, columns)| {
+                    !context.overflows(self.grid_size(rows, columns, width))
+                });
+                (width, layout.unwrap_or((count, 1)))
             }
-        } else {
-            for _ in 0..self.0 {
-                write!(context.writer, " ")?;
+            None => (0, (count, 1)),
+        };
+
+        for row in 0..rows {
+            if row > 0 {
+                context.newline()?;
+            }
+            let indices = self.row_indices(row, rows, columns);
+            for (i, &index) in indices.iter().enumerate() {
+                let item = &self.items[index];
+                let item_start = context.writer.column;
+                let mut item_context = context.reborrow();
+                item_context.trailing = Size::Size(0);
+                item_context.broken = item_context.overflows(item.size());
+                item.pretty_write(item_context)?;
+                if i + 1 < indices.len() {
+                    let item_width = context.writer.column.saturating_sub(item_start);
+                    for _ in item_width..width + COLUMN_GAP {
+                        write!(context.writer, " ")?;
+                    }
+                }
             }
         }
         Ok(())
     }
+
+    fn to_doc(&self) -> Doc {
+        let items: Vec<Doc> = self.items
+            .iter()
+            .map(|item| Doc::Group(Box::new(item.to_doc())))
+            .collect();
+        let width = match self.column_width() {
+            Some(width) => width,
+            None => return self.grid_doc(&items, items.len(), 1, 0),
+        };
+        // Try every distinct number of rows, starting with the fewest, and use the first layout
+        // which fits.
+        self.layouts()
+            .into_iter()
+            .rev()
+            .fold(None, |rest, (rows, columns)| {
+                let layout = self.grid_doc(&items, rows, columns, width);
+                Some(match rest {
+                    Some(rest) => Doc::Union(Box::new(layout), Box::new(rest)),
+                    None => layout,
+                })
+            })
+            .unwrap_or(Doc::Nil)
+    }
 }
 
-/// An unconditional newline.
+/// A wrapper which reflows text, breaking it between words so that each line fits within the
+/// maximum line length.
 ///
-/// Always causes its environment to break.
+/// The text is split on whitespace, and the words are separated by single spaces, or by a newline
+/// wherever the next word would not otherwise fit.  Any line breaks already in the text are
+/// ignored; use [`Paragraphs`] to preserve paragraph breaks.  A word which is longer than a whole
+/// line is never split.
 ///
 /// # Examples
 ///
 /// Basic usage:
 ///
 /// ```
-/// use pretty_trait::{JoinExt, Newline, to_string};
+/// use pretty_trait::{JoinExt, Words, Prefix, to_string};
+///
+/// let text = "Lorem ipsum dolor sit amet,\nconsectetur adipiscing elit.";
 ///
 /// let expected = "\
-/// hello
-/// world";
+/// // Lorem ipsum dolor
+/// // sit amet,
+/// // consectetur
+/// // adipiscing elit.";
 ///
-/// assert_eq!(to_string(&"hello".join(Newline).join("world"), None, 4), expected);
+/// assert_eq!(to_string(&"// ".join(Prefix("// ", Words(text))), Some(20), 4), expected);
 /// ```
+///
+/// [`Paragraphs`]: struct.Paragraphs.html
 #[derive(Clone, Copy, Debug)]
-pub struct Newline;
+pub struct Words<S>(pub S);
 
-impl Pretty for Newline {
+impl<S: AsRef<str>> Pretty for Words<S> {
     fn size(&self) -> Size {
-        Size::MultiLine
+        let mut words = self.0.as_ref().split_whitespace();
+        let first = words.next().map_or(0, |word| word.chars().count());
+        Size::Size(words.fold(first, |total, word| total + 1 + word.chars().count()))
     }
 
-    fn pretty_write(&self, context: Context) -> io::Result<()> {
-        writeln!(context.writer, "")?;
-        for _ in 0..(context.tab_size * context.indent_level) {
-            write!(context.writer, " ")?;
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        let mut words = self.0.as_ref().split_whitespace();
+        words.next().and_then(|first| {
+            words.next().map(|_| Size::Size(first.chars().count()))
+        })
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        let mut words = self.0.as_ref().split_whitespace().peekable();
+        let mut first = true;
+        while let Some(word) = words.next() {
+            let mut size = Size::Size(word.chars().count());
+            if words.peek().is_none() {
+                size = size + context.trailing;
+            }
+            if !first {
+                if context.exceeds_line(Size::Size(1) + size) {
+                    context.newline()?;
+                } else {
+                    write!(context.writer, " ")?;
+                }
+            }
+            write!(context.writer, "{}", word)?;
+            first = false;
         }
         Ok(())
     }
+
+    fn to_doc(&self) -> Doc {
+        let mut docs = Vec::new();
+        for (i, word) in self.0.as_ref().split_whitespace().enumerate() {
+            if i > 0 {
+                docs.push(Doc::Group(Box::new(Doc::Line(1))));
+            }
+            docs.push(Doc::Text(word.to_string()));
+        }
+        Doc::Concat(docs)
+    }
 }
 
-/// A wrapper which indents any newlines inside its contents.
+/// A wrapper which reflows text like [`Words`], but preserves the breaks between paragraphs.
+///
+/// Paragraphs are separated by one or more blank lines in the text, and are rendered separated by
+/// a single blank line.
 ///
 /// # Examples
 ///
 /// Basic usage:
 ///
 /// ```
-/// use pretty_trait::{JoinExt, Sep, Indent, to_string};
+/// use pretty_trait::{Paragraphs, to_string};
 ///
-/// let max_line = Some(20);
-/// let tab_size = 4;
+/// let text = "\
+/// The quick brown fox jumps
+/// over the lazy dog.
 ///
-/// let expected = "\
-/// (
-///     lorem
-///     ipsum
-///     dolor
-///     sit
-///     amet
-/// )";
 ///
-/// assert_eq!(
-///     to_string(
-///         &"(".join(Indent(
-///             Sep(0)
-///                 .join("lorem")
-///                 .join(Sep(1))
-///                 .join("ipsum")
-///                 .join(Sep(1))
-///                 .join("dolor")
-///                 .join(Sep(1))
-///                 .join("sit")
-///                 .join(Sep(1))
-///                 .join("amet")
-///         )).join(Sep(0)).join(")"),
-///         max_line,
-///         tab_size,
-///     ),
-///     expected
-/// );
-/// ```
+/// Pack my box with five dozen jugs.";
 ///
-/// # Caution
+/// let expected = "\
+/// The quick brown fox
+/// jumps over the lazy
+/// dog.
 ///
-/// To indent a block enclosed in paired delimiters like brackets, care must be taken to ensure that
-/// the first line of the content *is* indented, and that the closing delimiter *is not* indented
-/// along with its contents.  To ensure this, the newline after the opening delimiter should occur
-/// *inside* the `Indent` block, and the newline before the closing delimiter should occur *outside*
-/// the `Indent` block, as in the example above.  The [`block`] function implements this pattern.
+/// Pack my box with
+/// five dozen jugs.";
 ///
-/// [`block`]: fn.block.html
+/// assert_eq!(to_string(&Paragraphs(text), Some(20), 4), expected);
+/// ```
+///
+/// [`Words`]: struct.Words.html
 #[derive(Clone, Copy, Debug)]
-pub struct Indent<T>(pub T);
+pub struct Paragraphs<S>(pub S);
 
-impl<T: Pretty> Pretty for Indent<T> {
+impl<S: AsRef<str>> Paragraphs<S> {
+    fn paragraphs(&self) -> Vec<Words<String>> {
+        let mut paragraphs = Vec::new();
+        let mut current = String::new();
+        for line in self.0.as_ref().lines() {
+            if line.trim().is_empty() {
+                if !current.is_empty() {
+                    paragraphs.push(Words(mem::take(&mut current)));
+                }
+            } else {
+                current.push_str(line);
+                current.push('\n');
+            }
+        }
+        if !current.is_empty() {
+            paragraphs.push(Words(current));
+        }
+        paragraphs
+    }
+}
+
+impl<S: AsRef<str>> Pretty for Paragraphs<S> {
     fn size(&self) -> Size {
-        self.0.size()
+        let paragraphs = self.paragraphs();
+        match paragraphs.len() {
+            0 => Size::Size(0),
+            1 => paragraphs[0].size(),
+            _ => Size::MultiLine,
+        }
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        let paragraphs = self.paragraphs();
+        match paragraphs.len() {
+            0 => None,
+            1 => paragraphs[0].break_prefix(broken),
+            _ => Some(leading_size(&paragraphs[0], Size::Size(0), broken)),
+        }
     }
 
     fn pretty_write(&self, mut context: Context) -> io::Result<()> {
-        context.indent_level += 1;
-        self.0.pretty_write(context)
+        let paragraphs = self.paragraphs();
+        let trailing = context.trailing;
+        for (i, paragraph) in paragraphs.iter().enumerate() {
+            if i > 0 {
+                context.break_lines(2)?;
+            }
+            let mut paragraph_context = context.reborrow();
+            if i + 1 < paragraphs.len() {
+                paragraph_context.trailing = Size::Size(0);
+            } else {
+                paragraph_context.trailing = trailing;
+            }
+            paragraph.pretty_write(paragraph_context)?;
+        }
+        Ok(())
+    }
+
+    fn to_doc(&self) -> Doc {
+        let mut docs = Vec::new();
+        for (i, paragraph) in self.paragraphs().iter().enumerate() {
+            if i > 0 {
+                docs.push(Doc::HardLines(2));
+            }
+            docs.push(paragraph.to_doc());
+        }
+        Doc::Concat(docs)
     }
 }
 
@@ -494,11 +3497,26 @@ impl<T: Pretty, U: Pretty> Pretty for Join<T, U> {
         self.0.size() + self.1.size()
     }
 
+    fn broken_size(&self) -> Size {
+        self.0.broken_size() + self.1.broken_size()
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        match self.0.break_prefix(broken) {
+            Some(prefix) => Some(prefix),
+            None => self.1.break_prefix(broken).map(|prefix| size_in(&self.0, broken) + prefix),
+        }
+    }
+
     fn pretty_write(&self, mut context: Context) -> io::Result<()> {
-        self.0.pretty_write(context.reborrow())?;
+        self.0.pretty_write(context.followed_by(&self.1))?;
         self.1.pretty_write(context)?;
         Ok(())
     }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Concat(vec![self.0.to_doc(), self.1.to_doc()])
+    }
 }
 
 /// Allows `join` to be called on any `Pretty` type.
@@ -589,9 +3607,164 @@ impl<T: Pretty> JoinExt for T {
 /// ]);
 /// ```
 #[derive(Clone, Debug)]
-pub struct Seq<T>(pub Vec<T>);
+pub struct Seq<T>(pub Vec<T>);
+
+impl<T: Pretty> Pretty for Seq<T> {
+    fn size(&self) -> Size {
+        self.0.iter().fold(
+            Size::Size(0),
+            |total, item| total + item.size(),
+        )
+    }
+
+    fn broken_size(&self) -> Size {
+        self.0.iter().fold(
+            Size::Size(0),
+            |total, item| total + item.broken_size(),
+        )
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        sequence_break_prefix(&self.0, broken)
+    }
+
+    fn pretty_write(&self, context: Context) -> io::Result<()> {
+        write_sequence(&self.0, context, |item, item_context| item.pretty_write(item_context))
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Concat(self.0.iter().map(Pretty::to_doc).collect())
+    }
+}
+
+fn sequence_break_prefix<T: Pretty>(items: &[T], broken: bool) -> Option<Size> {
+    let mut preceding = Size::Size(0);
+    for item in items {
+        if let Some(prefix) = item.break_prefix(broken) {
+            return Some(preceding + prefix);
+        }
+        preceding = preceding + size_in(item, broken);
+    }
+    None
+}
+
+/// Render a sequence of values one after another, letting each one know the size of the text which
+/// follows it.
+fn write_sequence<T, F>(items: &[T], mut context: Context, mut write_item: F) -> io::Result<()>
+where
+    T: Pretty,
+    F: FnMut(&T, Context) -> io::Result<()>,
+{
+    let mut trailings = Vec::with_capacity(items.len());
+    let mut trailing = context.trailing;
+    for item in items.iter().rev() {
+        trailings.push(trailing);
+        trailing = leading_size(item, trailing, context.broken);
+    }
+    for (item, trailing) in items.iter().zip(trailings.into_iter().rev()) {
+        let mut item_context = context.reborrow();
+        item_context.trailing = trailing;
+        write_item(item, item_context)?;
+    }
+    Ok(())
+}
+
+/// A wrapper that concatenates a sequence of pretty-printable values, breaking between them only
+/// where necessary to keep each line within the maximum line length.
+///
+/// Each item is rendered as if it were wrapped in its own [`Group`], so any separators inside an
+/// item break only if the item and the text following it up to the next possible line break would
+/// not otherwise fit.  This packs as many items onto each line as possible, like the words in a
+/// paragraph.  A `Fill` is usually created with the [`fill`] function.
+///
+/// [`Group`]: struct.Group.html
+/// [`fill`]: fn.fill.html
+#[derive(Clone, Debug)]
+pub struct Fill<T>(pub Vec<T>);
+
+impl<T: Pretty> Pretty for Fill<T> {
+    fn size(&self) -> Size {
+        self.0.iter().fold(
+            Size::Size(0),
+            |total, item| total + item.size(),
+        )
+    }
+
+    fn broken_size(&self) -> Size {
+        self.0.iter().fold(
+            Size::Size(0),
+            |total, item| total + item.broken_size(),
+        )
+    }
+
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        sequence_break_prefix(&self.0, true)
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        // Each item may break on its own, so the items following it are measured up to their first
+        // separator.
+        context.broken = true;
+        write_sequence(&self.0, context, |item, mut item_context| {
+            item_context.broken = item_context.overflows(item.size());
+            item.pretty_write(item_context)
+        })
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::Concat(
+            self.0
+                .iter()
+                .map(|item| Doc::Group(Box::new(item.to_doc())))
+                .collect(),
+        )
+    }
+}
+
+/// A wrapper that lays out a sequence of arguments so that, if they do not fit on one line, the
+/// last argument can expand across multiple lines while the others stay on the first line.
+///
+/// A `HugLast` is rendered in the first of three layouts which fits:
+///
+/// 1. Every item on a single line.
+/// 2. Every item except the last on a single line, with the last item rendered as if it were
+///    wrapped in its own [`Group`].  This is only used if the earlier items fit on one line along
+///    with the text of the last item up to its first possible line break.
+/// 3. Each item on its own indented line, as if the items were wrapped in [`block`].
+///
+/// This is intended for calls whose last argument is a closure or other block, which should be
+/// formatted as `foo(a, b, |x| {` rather than breaking every argument onto its own line.  A
+/// `HugLast` is usually created with the [`hug_last`] function.  Like a `Group`, it chooses its
+/// layout from the sizes of its items, so nested `HugLast`s each decide for themselves.
+///
+/// # Examples
+///
+/// Hugging the last argument of nested calls:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Indent, Newline, Sep, hug_last, to_string};
+///
+/// let closure = "|x| {".join(Indent(Newline.join("x + 1"))).join(Newline).join("}");
+/// let inner: Vec<Box<dyn Pretty>> = vec![Box::new("b"), Box::new(closure)];
+/// let inner = "g(".join(hug_last(&",".join(Sep(1)), inner)).join(")");
+/// let outer: Vec<Box<dyn Pretty>> = vec![Box::new("a"), Box::new(inner)];
+/// let call = "f(".join(hug_last(&",".join(Sep(1)), outer)).join(")");
+///
+/// let expected = "\
+/// f(a, g(b, |x| {
+///     x + 1
+/// }))";
+///
+/// assert_eq!(to_string(&call, Some(20), 4), expected);
+/// ```
+///
+/// [`Group`]: struct.Group.html
+/// [`block`]: fn.block.html
+/// [`hug_last`]: fn.hug_last.html
+#[derive(Clone, Debug)]
+pub struct HugLast<T>(pub Vec<T>);
 
-impl<T: Pretty> Pretty for Seq<T> {
+impl<T: Pretty> Pretty for HugLast<T> {
     fn size(&self) -> Size {
         self.0.iter().fold(
             Size::Size(0),
@@ -599,32 +3772,118 @@ impl<T: Pretty> Pretty for Seq<T> {
         )
     }
 
+    fn break_prefix(&self, _broken: bool) -> Option<Size> {
+        sequence_break_prefix(&self.0, true)
+    }
+
     fn pretty_write(&self, mut context: Context) -> io::Result<()> {
-        for item in &self.0 {
-            item.pretty_write(context.reborrow())?;
+        let last = match self.0.last() {
+            Some(last) => last,
+            None => return Ok(()),
+        };
+        context.inconsistent = false;
+        context.priority = None;
+        if !context.overflows(self.size()) {
+            context.broken = false;
+            return write_sequence(&self.0, context, |item, item_context| {
+                item.pretty_write(item_context)
+            });
         }
-        Ok(())
+
+        // As with a `Group`, the second layout is chosen from sizes alone: the earlier items must
+        // fit on the current line along with the start of the last item.
+        let hugged = Hugged(&self.0);
+        let leading = hugged.size() + leading_size(last, context.trailing, true);
+        if !hugged.size().is_multi_line() && !context.exceeds_line(leading) {
+            return hugged.pretty_write(context);
+        }
+
+        context.broken = true;
+        {
+            let mut inner = context.reborrow();
+            inner.indent_level += 1;
+            inner.indent = inner.indent.with_spaces(inner.tab_size);
+            inner.trailing = Size::Size(0);
+            inner.newline()?;
+            write_sequence(&self.0, inner, |item, item_context| {
+                item.pretty_write(item_context)
+            })?;
+        }
+        context.newline()
+    }
+
+    fn to_doc(&self) -> Doc {
+        let items: Vec<Doc> = self.0.iter().map(Pretty::to_doc).collect();
+        let mut init = items.clone();
+        let last = init.pop().map_or(Doc::Nil, |last| Doc::Group(Box::new(last)));
+        let hugged = Doc::Concat(vec![Doc::Flat(Box::new(Doc::Concat(init))), last]);
+        let broken = Doc::Broken(Box::new(Doc::Concat(vec![
+            Doc::Nest(1, Box::new(Doc::Concat(vec![Doc::Line(0), Doc::Concat(items.clone())]))),
+            Doc::Line(0),
+        ])));
+        Doc::Union(
+            Box::new(Doc::Flat(Box::new(Doc::Concat(items)))),
+            Box::new(Doc::Union(Box::new(hugged), Box::new(broken))),
+        )
+    }
+}
+
+/// The second layout of a `HugLast`.  Its size is that of every item except the last, since the
+/// last item is allowed to break.
+struct Hugged<'a, T: 'a>(&'a [T]);
+
+impl<'a, T: Pretty> Pretty for Hugged<'a, T> {
+    fn size(&self) -> Size {
+        match self.0.split_last() {
+            Some((_, init)) => init.iter().fold(Size::Size(0), |total, item| total + item.size()),
+            None => Size::Size(0),
+        }
+    }
+
+    fn pretty_write(&self, mut context: Context) -> io::Result<()> {
+        let (last, init) = match self.0.split_last() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+        let trailing = context.trailing;
+        context.broken = false;
+        context.trailing = leading_size(last, trailing, true);
+        write_sequence(init, context.reborrow(), |item, item_context| {
+            item.pretty_write(item_context)
+        })?;
+        context.trailing = trailing;
+        context.broken = context.overflows(last.size());
+        last.pretty_write(context)
+    }
+}
+
+/// Calculate the size of a value up to its first possible line break, or of the whole value
+/// followed by `trailing` if it cannot break, in an environment which may or may not be broken.
+fn leading_size<T: Pretty + ?Sized>(value: &T, trailing: Size, broken: bool) -> Size {
+    value
+        .break_prefix(broken)
+        .unwrap_or_else(|| size_in(value, broken) + trailing)
+}
+
+/// Calculate the size of a value in an environment which may or may not be broken.
+fn size_in<T: Pretty + ?Sized>(value: &T, broken: bool) -> Size {
+    if broken {
+        value.broken_size()
+    } else {
+        value.size()
     }
 }
 
 /// Render a pretty-printable value to an arbitrary `io::Write` handle.
 ///
 /// This is the most general way to render a `Pretty` type.
-pub fn write<T: Pretty>(
-    writer: &mut io::Write,
+pub fn write<T: Pretty + ?Sized>(
+    writer: &mut dyn io::Write,
     content: &T,
     max_line: Option<usize>,
     tab_size: usize,
 ) -> io::Result<()> {
-    let size = content.size();
-    let context = Context {
-        max_line,
-        tab_size,
-        indent_level: 0,
-        broken: size.exceeds(max_line),
-        writer,
-    };
-    content.pretty_write(context)
+    write_with_ribbon(writer, content, max_line, None, tab_size)
 }
 
 /// Render a pretty-printable value to an owned string and return it.
@@ -641,18 +3900,189 @@ pub fn write<T: Pretty>(
 ///
 /// [`println_simple`]: fn.println_simple.html
 /// [`write`]: fn.write.html
-pub fn to_string<T: Pretty>(content: &T, max_line: Option<usize>, tab_size: usize) -> String {
+pub fn to_string<T: Pretty + ?Sized>(
+    content: &T,
+    max_line: Option<usize>,
+    tab_size: usize,
+) -> String {
     let mut result = Vec::new();
     write(&mut result, content, max_line, tab_size).expect("Writing to a string should not fail");
     String::from_utf8(result).expect("Invalid UTF8")
 }
 
+/// Render a pretty-printable value to an arbitrary `io::Write` handle, limiting the number of
+/// characters on each line not counting indentation, as well as the total line length.
+///
+/// With deep nesting, `max_line` alone allows lines which are densely packed all the way from their
+/// indentation to the maximum line length.  Passing a `ribbon` width causes a group to break if any
+/// of its lines would contain more than `ribbon` characters after the indentation, even if it would
+/// fit within `max_line`.  Passing `None` is equivalent to calling [`write`].  The renderers in the
+/// [`doc`] module take a ribbon width in the same way, through [`doc::write_with_ribbon`] and
+/// [`write_optimal_with_ribbon`].
+///
+/// [`write`]: fn.write.html
+/// [`doc`]: doc/index.html
+/// [`doc::write_with_ribbon`]: doc/fn.write_with_ribbon.html
+/// [`write_optimal_with_ribbon`]: fn.write_optimal_with_ribbon.html
+pub fn write_with_ribbon<T: Pretty + ?Sized>(
+    writer: &mut dyn io::Write,
+    content: &T,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> io::Result<()> {
+    let size = content.size();
+    let mut writer = Writer::new(writer);
+    let mut context = Context {
+        max_line,
+        tab_size,
+        indent_level: 0,
+        indent: Indentation::new(),
+        broken: false,
+        inconsistent: false,
+        ribbon,
+        priority: None,
+        trailing: Size::Size(0),
+        writer: &mut writer,
+    };
+    context.broken = context.overflows(size);
+    content.pretty_write(context)?;
+    writer.finish()
+}
+
+/// Render a pretty-printable value to an owned string using [`write_with_ribbon`], and return it.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, IndentBy, Newline, Pretty, Sep, to_string, to_string_with_ribbon};
+/// use pretty_trait::{doc, to_string_optimal_with_ribbon};
+///
+/// let call = Group::new("foo(a,".join(Sep(1)).join("b)"));
+/// let nested = "{".join(IndentBy(8, Newline.join(call))).join(Newline).join("}");
+///
+/// // The call fits within the maximum line length:
+///
+/// let expected_unbroken = "\
+/// {
+///         foo(a, b)
+/// }";
+///
+/// assert_eq!(to_string(&nested, Some(20), 4), expected_unbroken);
+///
+/// // But not within the ribbon width once its indentation is discounted:
+///
+/// let expected_broken = "\
+/// {
+///         foo(a,
+///         b)
+/// }";
+///
+/// assert_eq!(to_string_with_ribbon(&nested, Some(20), Some(8), 4), expected_broken);
+///
+/// // The doc renderers break it in the same way:
+///
+/// assert_eq!(doc::to_string_with_ribbon(&nested.to_doc(), Some(20), Some(8), 4), expected_broken);
+/// assert_eq!(to_string_optimal_with_ribbon(&nested, Some(20), Some(8), 4), expected_broken);
+/// ```
+///
+/// [`write_with_ribbon`]: fn.write_with_ribbon.html
+pub fn to_string_with_ribbon<T: Pretty + ?Sized>(
+    content: &T,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> String {
+    let mut result = Vec::new();
+    write_with_ribbon(&mut result, content, max_line, ribbon, tab_size)
+        .expect("Writing to a string should not fail");
+    String::from_utf8(result).expect("Invalid UTF8")
+}
+
+/// Render a pretty-printable value to an arbitrary `io::Write` handle, searching for the layout
+/// with the least overflow beyond `max_line`, and then the fewest lines.
+///
+/// Where [`write`] decides whether to break each `Group` greedily, from a single size comparison,
+/// this considers every combination of broken and unbroken groups, so it can find a layout that
+/// overflows less than the one the greedy strategy picks.  It works by lowering `content` to a
+/// [`Doc`], and is considerably slower than [`write`].  See [`doc::write_optimal`] for details.
+///
+/// # Examples
+///
+/// Keeping a group flat when breaking it would only move its text somewhere it overflows further:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Indent, Sep, to_string, to_string_optimal};
+///
+/// let max_line = Some(15);
+/// let tab_size = 4;
+///
+/// let condition = Group::new("if".join(Indent(Sep(1).join("ready_to_go()"))));
+///
+/// assert_eq!(to_string(&condition, max_line, tab_size), "if\n    ready_to_go()");
+/// assert_eq!(to_string_optimal(&condition, max_line, tab_size), "if ready_to_go()");
+/// ```
+///
+/// [`write`]: fn.write.html
+/// [`Doc`]: doc/enum.Doc.html
+/// [`doc::write_optimal`]: doc/fn.write_optimal.html
+pub fn write_optimal<T: Pretty + ?Sized>(
+    writer: &mut dyn io::Write,
+    content: &T,
+    max_line: Option<usize>,
+    tab_size: usize,
+) -> io::Result<()> {
+    doc::write_optimal(writer, &content.to_doc(), max_line, tab_size)
+}
+
+/// Render a pretty-printable value to an owned string using [`write_optimal`], and return it.
+///
+/// [`write_optimal`]: fn.write_optimal.html
+pub fn to_string_optimal<T: Pretty + ?Sized>(
+    content: &T,
+    max_line: Option<usize>,
+    tab_size: usize,
+) -> String {
+    doc::to_string_optimal(&content.to_doc(), max_line, tab_size)
+}
+
+/// Render a pretty-printable value to an arbitrary `io::Write` handle like [`write_optimal`], also
+/// counting any characters beyond the `ribbon` width on each line, not counting indentation, as
+/// overflow.  See [`write_with_ribbon`] for the meaning of the ribbon width.
+///
+/// [`write_optimal`]: fn.write_optimal.html
+/// [`write_with_ribbon`]: fn.write_with_ribbon.html
+pub fn write_optimal_with_ribbon<T: Pretty + ?Sized>(
+    writer: &mut dyn io::Write,
+    content: &T,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> io::Result<()> {
+    doc::write_optimal_with_ribbon(writer, &content.to_doc(), max_line, ribbon, tab_size)
+}
+
+/// Render a pretty-printable value to an owned string using [`write_optimal_with_ribbon`], and
+/// return it.
+///
+/// [`write_optimal_with_ribbon`]: fn.write_optimal_with_ribbon.html
+pub fn to_string_optimal_with_ribbon<T: Pretty + ?Sized>(
+    content: &T,
+    max_line: Option<usize>,
+    ribbon: Option<usize>,
+    tab_size: usize,
+) -> String {
+    doc::to_string_optimal_with_ribbon(&content.to_doc(), max_line, ribbon, tab_size)
+}
+
 /// Conveniently render a pretty-printable value to standard output.
 ///
 /// This function uses a default maximum line length of 80 characters, and a tab size of 2 spaces.
 pub fn println_simple<T: Pretty>(content: &T) {
     write(&mut io::stdout(), content, Some(80), 2).unwrap();
-    println!("");
+    println!();
 }
 
 /// A wrapper which decides whether or not to render its contents based on the breaking mode of the
@@ -697,6 +4127,40 @@ pub fn println_simple<T: Pretty>(content: &T) {
 ///     "lorem, ipsum, dolor, sit, amet"
 /// );
 /// ```
+///
+/// When measured, a `Conditional` reports the size it takes up when rendered on a single line, so
+/// the contents of `Always` and `OnlyUnbroken` are counted, while those of `OnlyBroken` are not.
+/// This means that a group breaks whenever its unbroken rendering would not fit:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Sep, Conditional, to_string};
+///
+/// let call = Group::new(
+///     "f(".join(Sep(0)).join("x").join(Conditional::OnlyUnbroken(" /* unused */")).join(")"),
+/// );
+///
+/// assert_eq!(to_string(&call, Some(20), 4), "f(x /* unused */)");
+/// assert_eq!(to_string(&call, Some(16), 4), "f(\nx)");
+/// ```
+///
+/// Text following a `Conditional` is measured according to the environment it appears in, so the
+/// trailing comma here counts towards the line of the group before it, because the list is broken:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, Sep, Indent, Conditional, block, to_string};
+///
+/// let item = Group::new("aaaa".join(Sep(1)).join("bbbb"));
+/// let comma = Conditional::OnlyBroken(",");
+/// let list = Group::always_broken("[".join(block(item.join(comma)))).join("]");
+///
+/// assert_eq!(to_string(&list, Some(14), 4), "[\n    aaaa bbbb,\n]");
+/// assert_eq!(to_string(&list, Some(13), 4), "[\n    aaaa\n    bbbb,\n]");
+///
+/// let comma = Indent(Conditional::OnlyBroken(","));
+/// let list = Group::always_broken("[".join(block(item.join(comma)))).join("]");
+///
+/// assert_eq!(to_string(&list, Some(13), 4), "[\n    aaaa\n    bbbb,\n]");
+/// ```
 #[derive(Clone, Copy, Debug)]
 pub enum Conditional<T> {
     /// Render the wrapped value under all circumstances
@@ -711,17 +4175,145 @@ pub enum Conditional<T> {
 
 impl<T: Pretty> Pretty for Conditional<T> {
     fn size(&self) -> Size {
-        Size::Size(0)
+        match self {
+            Conditional::Always(inner) | Conditional::OnlyUnbroken(inner) => inner.size(),
+            Conditional::OnlyBroken(_) => Size::Size(0),
+        }
+    }
+
+    fn broken_size(&self) -> Size {
+        match self {
+            Conditional::Always(inner) | Conditional::OnlyBroken(inner) => inner.broken_size(),
+            Conditional::OnlyUnbroken(_) => Size::Size(0),
+        }
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        match self {
+            Conditional::Always(inner) => inner.break_prefix(broken),
+            Conditional::OnlyBroken(inner) if broken => inner.break_prefix(true),
+            Conditional::OnlyUnbroken(inner) if !broken => inner.break_prefix(false),
+            _ => None,
+        }
     }
 
     fn pretty_write(&self, context: Context) -> io::Result<()> {
         match (self, context.broken) {
-            (&Conditional::Always(ref inner), _) |
-            (&Conditional::OnlyBroken(ref inner), true) |
-            (&Conditional::OnlyUnbroken(ref inner), false) => inner.pretty_write(context),
+            (Conditional::Always(inner), _) |
+            (Conditional::OnlyBroken(inner), true) |
+            (Conditional::OnlyUnbroken(inner), false) => inner.pretty_write(context),
             _ => Ok(()),
         }
     }
+
+    fn to_doc(&self) -> Doc {
+        match self {
+            Conditional::Always(inner) => inner.to_doc(),
+            Conditional::OnlyBroken(inner) => {
+                Doc::FlatAlt(Box::new(inner.to_doc()), Box::new(Doc::Nil))
+            }
+            Conditional::OnlyUnbroken(inner) => {
+                Doc::FlatAlt(Box::new(Doc::Nil), Box::new(inner.to_doc()))
+            }
+        }
+    }
+}
+
+/// A unique identifier for a [`Group`], which allows other values to depend on whether or not it
+/// was broken.
+///
+/// See [`IfBroken`] for an example.
+///
+/// [`Group`]: struct.Group.html
+/// [`IfBroken`]: struct.IfBroken.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GroupId(usize);
+
+impl GroupId {
+    /// Create a new identifier, distinct from every other `GroupId`.
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        GroupId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for GroupId {
+    fn default() -> Self {
+        GroupId::new()
+    }
+}
+
+/// A wrapper which renders one of two values depending on whether or not a particular [`Group`]
+/// was broken.
+///
+/// `IfBroken(id, then, otherwise)` renders `then` if the group labeled with `id` (using
+/// [`Group::with_id`]) was broken, or `otherwise` if it was not.  The group must be rendered before
+/// the `IfBroken` which refers to it; until then, `otherwise` is rendered.  Unlike a
+/// [`Conditional`], which depends on the innermost enclosing group, an `IfBroken` can depend on
+/// any group, such as a sibling.
+///
+/// When measured, an `IfBroken` reports the size of `otherwise`, since the group it depends on has
+/// usually not been laid out yet.
+///
+/// # Examples
+///
+/// Opening a brace on its own line only when a function signature is broken:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Group, GroupId, IfBroken, Newline, Sep};
+/// use pretty_trait::{delimited, block, to_string};
+///
+/// let signature = |params: &[&'static str]| {
+///     let id = GroupId::new();
+///     Group::new("fn f(".join(block(delimited(&",".join(Sep(1)), params.to_vec()))).join(")"))
+///         .with_id(id)
+///         .join(IfBroken(id, Newline, Sep(1)))
+///         .join("{}")
+/// };
+///
+/// assert_eq!(to_string(&signature(&["a", "b"]), Some(20), 4), "fn f(a, b) {}");
+///
+/// let expected = "\
+/// fn f(
+///     alpha,
+///     beta,
+///     gamma
+/// )
+/// {}";
+///
+/// assert_eq!(to_string(&signature(&["alpha", "beta", "gamma"]), Some(20), 4), expected);
+/// ```
+///
+/// [`Group`]: struct.Group.html
+/// [`Group::with_id`]: struct.Group.html#method.with_id
+/// [`Conditional`]: enum.Conditional.html
+#[derive(Clone, Copy, Debug)]
+pub struct IfBroken<T, U>(pub GroupId, pub T, pub U);
+
+impl<T: Pretty, U: Pretty> Pretty for IfBroken<T, U> {
+    fn size(&self) -> Size {
+        self.2.size()
+    }
+
+    fn broken_size(&self) -> Size {
+        self.2.broken_size()
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        self.2.break_prefix(broken)
+    }
+
+    fn pretty_write(&self, context: Context) -> io::Result<()> {
+        if context.writer.group_broken(self.0) == Some(true) {
+            self.1.pretty_write(context)
+        } else {
+            self.2.pretty_write(context)
+        }
+    }
+
+    fn to_doc(&self) -> Doc {
+        Doc::IfBroken(self.0, Box::new(self.1.to_doc()), Box::new(self.2.to_doc()))
+    }
 }
 
 /// An `Option` will render its contents if it is `Some`, or an empty string if it is `None`.
@@ -770,15 +4362,36 @@ impl<T: Pretty> Pretty for Conditional<T> {
 impl<T: Pretty> Pretty for Option<T> {
     fn size(&self) -> Size {
         match self {
-            &Some(ref inner) => inner.size(),
-            &None => Size::Size(0),
+            Some(inner) => inner.size(),
+            None => Size::Size(0),
+        }
+    }
+
+    fn broken_size(&self) -> Size {
+        match self {
+            Some(inner) => inner.broken_size(),
+            None => Size::Size(0),
+        }
+    }
+
+    fn break_prefix(&self, broken: bool) -> Option<Size> {
+        match self {
+            Some(inner) => inner.break_prefix(broken),
+            None => None,
         }
     }
 
     fn pretty_write(&self, context: Context) -> io::Result<()> {
         match self {
-            &Some(ref inner) => inner.pretty_write(context),
-            &None => Ok(()),
+            Some(inner) => inner.pretty_write(context),
+            None => Ok(()),
+        }
+    }
+
+    fn to_doc(&self) -> Doc {
+        match self {
+            Some(inner) => inner.to_doc(),
+            None => Doc::Nil,
         }
     }
 }
@@ -818,6 +4431,96 @@ where
     Seq(results)
 }
 
+/// Separate a sequence of pretty-printable values by a delimiter, packing as many of them onto each
+/// line as will fit.
+///
+/// The delimiter is not included on the last item.  This is like [`delimited`], except that each
+/// separator inside the delimiter breaks independently, only where necessary, instead of all of
+/// them breaking together.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{JoinExt, Sep, fill, to_string};
+///
+/// let max_line = Some(12);
+/// let tab_size = 4;
+///
+/// let numbers: Vec<String> = (1..11).map(|i| i.to_string()).collect();
+///
+/// let expected = "\
+/// 1, 2, 3, 4,
+/// 5, 6, 7, 8,
+/// 9, 10";
+///
+/// assert_eq!(
+///     to_string(&fill(&",".join(Sep(1)), numbers), max_line, tab_size),
+///     expected
+/// );
+/// ```
+///
+/// [`delimited`]: fn.delimited.html
+pub fn fill<Delim, Item, It>(delim: &Delim, it: It) -> Fill<Join<Item, Option<Delim>>>
+where
+    Delim: Pretty + Clone,
+    Item: Pretty,
+    It: IntoIterator<Item = Item>,
+{
+    Fill(delimited(delim, it).0)
+}
+
+/// Separate a sequence of pretty-printable values by a delimiter, letting the last of them expand
+/// across multiple lines while the others stay on the first line.
+///
+/// The delimiter is not included on the last item.  See [`HugLast`] for how the values are laid
+/// out.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use pretty_trait::{Pretty, JoinExt, Indent, Newline, Sep, hug_last, to_string};
+///
+/// let tab_size = 4;
+///
+/// let closure = "|x| {".join(Indent(Newline.join("x + 1"))).join(Newline).join("}");
+/// let args: Vec<Box<dyn Pretty>> = vec![Box::new("a"), Box::new("b"), Box::new(closure)];
+/// let call = "foo(".join(hug_last(&",".join(Sep(1)), args)).join(")");
+///
+/// let expected_hugged = "\
+/// foo(a, b, |x| {
+///     x + 1
+/// })";
+///
+/// assert_eq!(to_string(&call, Some(20), tab_size), expected_hugged);
+///
+/// // When the first line does not fit, every argument is broken onto its own line:
+///
+/// let expected_broken = "\
+/// foo(
+///     a,
+///     b,
+///     |x| {
+///         x + 1
+///     }
+/// )";
+///
+/// assert_eq!(to_string(&call, Some(14), tab_size), expected_broken);
+/// ```
+///
+/// [`HugLast`]: struct.HugLast.html
+pub fn hug_last<Delim, Item, It>(delim: &Delim, it: It) -> HugLast<Join<Item, Option<Delim>>>
+where
+    Delim: Pretty + Clone,
+    Item: Pretty,
+    It: IntoIterator<Item = Item>,
+{
+    HugLast(delimited(delim, it).0)
+}
+
 /// Wrap a pretty-printable value so that it will display as an indented block when broken across
 /// multiple lines.
 ///